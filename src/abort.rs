@@ -0,0 +1,197 @@
+use crate::{Error, Result};
+use futures_util::future::{self, Either};
+use futures_util::stream::{self, StreamExt};
+use http_kit::Body;
+use std::future::{poll_fn, Future};
+use std::io;
+use std::pin::pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
+
+/// A handle that cancels an in-flight request, possibly from another task.
+///
+/// Aborting drops the underlying backend future, which tears down its connection,
+/// and makes the pending [`ResponseFuture`](crate::ResponseFuture) resolve to
+/// [`Error::Aborted`](crate::Error::Aborted). Like any other failure, that's reported to
+/// [observers](crate::ClientObserver::on_error) and the tracing span. Once the response has
+/// arrived, aborting ends its body instead: reading it fails with an I/O error wrapping
+/// [`Error::Aborted`].
+#[derive(Debug, Clone, Default)]
+pub struct AbortHandle {
+    inner: Arc<Inner>,
+}
+
+/// The response and its body wait for the abort in different tasks, so each gets a waker.
+#[derive(Debug, Default)]
+struct Inner {
+    aborted: AtomicBool,
+    response: Mutex<Option<Waker>>,
+    body: Mutex<Option<Waker>>,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Waiter {
+    Response,
+    Body,
+}
+
+impl Inner {
+    fn waker(&self, waiter: Waiter) -> MutexGuard<'_, Option<Waker>> {
+        let waker = match waiter {
+            Waiter::Response => &self.response,
+            Waiter::Body => &self.body,
+        };
+        waker.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl AbortHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn abort(&self) {
+        self.inner.aborted.store(true, Ordering::SeqCst);
+        for waiter in [Waiter::Response, Waiter::Body] {
+            if let Some(waker) = self.inner.waker(waiter).take() {
+                waker.wake();
+            }
+        }
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.inner.aborted.load(Ordering::SeqCst)
+    }
+
+    fn poll_aborted(&self, cx: &mut Context<'_>, waiter: Waiter) -> Poll<()> {
+        if self.is_aborted() {
+            return Poll::Ready(());
+        }
+        *self.inner.waker(waiter) = Some(cx.waker().clone());
+        // `abort` may have run between the check and registering the waker.
+        match self.is_aborted() {
+            true => Poll::Ready(()),
+            false => Poll::Pending,
+        }
+    }
+
+    /// Run `future` until it completes or this handle is aborted, in which case it's dropped
+    /// right away and [`Error::Aborted`] is returned.
+    pub(crate) async fn run<T>(&self, future: impl Future<Output = Result<T>>) -> Result<T> {
+        let aborted = pin!(poll_fn(|cx| self.poll_aborted(cx, Waiter::Response)));
+        match future::select(pin!(future), aborted).await {
+            Either::Left((result, _)) => result,
+            Either::Right(_) => Err(Error::Aborted),
+        }
+    }
+
+    /// End `body` as soon as this handle is aborted, even while it waits for data.
+    pub(crate) fn guard(self, body: Body) -> Body {
+        let body = stream::unfold(Some(body), move |body| {
+            let handle = self.clone();
+            async move {
+                let mut body = body?;
+                let aborted = pin!(poll_fn(|cx| handle.poll_aborted(cx, Waiter::Body)));
+                match future::select(body.next(), aborted).await {
+                    Either::Left((Some(Ok(chunk)), _)) => Some((Ok(chunk), Some(body))),
+                    Either::Left((Some(Err(error)), _)) => {
+                        let error = io::Error::other(error.to_string());
+                        Some((Err(error), None))
+                    }
+                    Either::Left((None, _)) => None,
+                    // Dropping the body tears down the connection it's read from.
+                    Either::Right(_) => {
+                        let error = io::Error::new(io::ErrorKind::Interrupted, Error::Aborted);
+                        Some((Err(error), None))
+                    }
+                }
+            }
+        });
+        Body::from_stream(body)
+    }
+
+    /// Whether a request still holds this handle.
+    pub(crate) fn in_use(&self) -> bool {
+        Arc::strong_count(&self.inner) > 1
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{BodySender, Client, ClientBackend, ClientObserver, Error};
+    use async_trait::async_trait;
+    use futures_util::StreamExt;
+    use http_kit::{Body, Endpoint, Method, Request, Response, Uri};
+    use hyper::http;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// Never answers `/head`, and answers anything else with a body that never ends.
+    #[derive(Default)]
+    struct Stalled(Mutex<Vec<BodySender>>);
+
+    #[async_trait]
+    impl Endpoint for Stalled {
+        async fn call_endpoint(&self, request: &mut Request) -> http_kit::Result<Response> {
+            if request.uri().path() == "/head" {
+                std::future::pending::<()>().await;
+            }
+            let (sender, body) = BodySender::channel();
+            sender.send("partial").await.unwrap();
+            self.0.lock().unwrap().push(sender);
+            Ok(http::Response::new(body).into())
+        }
+    }
+
+    impl ClientBackend for Stalled {}
+
+    #[tokio::test]
+    async fn abort_before_response() {
+        let client = Client::with_backend(Stalled::default());
+        let mut request = client.get("http://example.com/head");
+        let handle = request.abort_handle();
+        tokio::spawn(async move {
+            tokio::task::yield_now().await;
+            handle.abort();
+        });
+        assert!(matches!(request.await, Err(Error::Aborted)));
+    }
+
+    #[tokio::test]
+    async fn abort_during_body() {
+        let client = Client::with_backend(Stalled::default());
+        let mut request = client.get("http://example.com/body");
+        let handle = request.abort_handle();
+        let mut response = request.await.unwrap();
+        let mut body = response.replace_body(Body::empty());
+        assert_eq!(&body.next().await.unwrap().unwrap()[..], b"partial");
+
+        tokio::spawn(async move {
+            tokio::task::yield_now().await;
+            handle.abort();
+        });
+        let error = body.next().await.unwrap().unwrap_err();
+        assert!(error.to_string().contains("request aborted"), "{error}");
+        assert!(body.next().await.is_none());
+    }
+
+    #[derive(Default)]
+    struct Errors(Mutex<Vec<String>>);
+
+    impl ClientObserver for Arc<Errors> {
+        fn on_error(&self, _method: &Method, _uri: &Uri, error: &Error, _elapsed: Duration) {
+            self.0.lock().unwrap().push(error.to_string());
+        }
+    }
+
+    #[tokio::test]
+    async fn observers_see_aborted_requests() {
+        let errors = Arc::new(Errors::default());
+        let client = Client::with_backend(Stalled::default()).observer(errors.clone());
+        let mut request = client.get("http://example.com/head");
+        request.abort_handle().abort();
+        assert!(matches!(request.await, Err(Error::Aborted)));
+        assert_eq!(*errors.0.lock().unwrap(), ["request aborted"]);
+    }
+}
@@ -0,0 +1,133 @@
+use crate::cookie_store::ArchivedCookie;
+use crate::{Client, ClientBackend, Error, Result};
+use serde::{Deserialize, Serialize};
+use std::sync::PoisonError;
+
+const VERSION: u32 = 1;
+
+/// A portable snapshot of a client's cookie store, for pre-warming clients in other processes
+/// or shipping fixtures.
+///
+/// Serialize it with any serde format. The format is versioned, so archives written by older
+/// releases keep loading.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Archive {
+    version: u32,
+    cookies: Vec<ArchivedCookie>,
+}
+
+impl<B: ClientBackend> Client<B> {
+    /// Export the cookies this client has stored. Expired cookies are left out.
+    pub fn export(&self) -> Archive {
+        Archive {
+            version: VERSION,
+            cookies: self
+                .cookies
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .export(),
+        }
+    }
+
+    /// Load an archive written by [`Client::export`].
+    ///
+    /// Archived cookies replace stored ones with the same name, domain and path, and the
+    /// remaining stored cookies are kept.
+    pub fn import(&self, archive: Archive) -> Result<()> {
+        if archive.version > VERSION {
+            return Err(Error::UnsupportedArchive(archive.version));
+        }
+        self.cookies
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .import(archive.cookies);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Archive;
+    use crate::{Client, ClientBackend, Error};
+    use http_kit::Uri;
+    use std::sync::PoisonError;
+
+    fn header<B: ClientBackend>(client: &Client<B>, uri: &'static str) -> Option<String> {
+        client
+            .cookies
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .header(&Uri::from_static(uri), false)
+    }
+
+    #[test]
+    fn round_trip() {
+        let client = Client::new();
+        client
+            .cookies
+            .write()
+            .unwrap_or_else(PoisonError::into_inner)
+            .store(
+                &Uri::from_static("http://example.com/"),
+                "host=1, domain=2; Domain=example.com; Max-Age=3600",
+                false,
+            );
+
+        let json = serde_json::to_string(&client.export()).unwrap();
+        let archive: Archive = serde_json::from_str(&json).unwrap();
+        let loaded = Client::new();
+        loaded.import(archive).unwrap();
+
+        assert_eq!(
+            header(&loaded, "http://example.com/").as_deref(),
+            Some("host=1; domain=2")
+        );
+        // Host-only cookies stay with their host.
+        assert_eq!(
+            header(&loaded, "http://www.example.com/").as_deref(),
+            Some("domain=2")
+        );
+        assert_eq!(
+            serde_json::to_string(&loaded.export()).unwrap(),
+            json,
+            "a loaded archive exports the same"
+        );
+    }
+
+    #[test]
+    fn expired_cookies_are_dropped() {
+        let cookie = |name: &str, expires: Option<i64>| {
+            serde_json::json!({
+                "name": name,
+                "value": "1",
+                "domain": "example.com",
+                "path": "/",
+                "host_only": true,
+                "secure": false,
+                "http_only": false,
+                "expires": expires,
+            })
+        };
+        let archive = serde_json::json!({
+            "version": 1,
+            "cookies": [cookie("expired", Some(1)), cookie("fresh", Some(i64::from(u32::MAX)))],
+        });
+        let client = Client::new();
+        client
+            .import(serde_json::from_value(archive).unwrap())
+            .unwrap();
+        assert_eq!(
+            header(&client, "http://example.com/").as_deref(),
+            Some("fresh=1")
+        );
+        let exported = serde_json::to_value(client.export()).unwrap();
+        assert_eq!(exported["cookies"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn newer_versions_are_rejected() {
+        let archive = serde_json::json!({ "version": 2, "cookies": [] });
+        let result = Client::new().import(serde_json::from_value(archive).unwrap());
+        assert!(matches!(result, Err(Error::UnsupportedArchive(2))));
+    }
+}
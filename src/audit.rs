@@ -0,0 +1,284 @@
+//! A hash-chained log of the requests a client sends.
+//!
+//! Register [`Audit`] as middleware and every request is appended to the log as a line of JSON
+//! before it's sent. Each [`AuditRecord`] includes the hash of the one before it, so editing,
+//! removing or reordering records breaks the chain, which [`verify`] detects.
+//!
+//! The hashes aren't keyed: whoever can rewrite the log can also recompute the chain, and
+//! records cut off its end leave no trace. To detect that, keep the hash of the latest record
+//! somewhere the log's writers can't change, and check the chain ends with it.
+
+use crate::{DryRun, Error, Middleware, Next, Result, Streaming};
+use async_trait::async_trait;
+use http_kit::{Request, Response};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fmt::{self, Debug, Write as _};
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The `previous` hash of the first record of a log.
+const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Who a request is sent on behalf of, recorded by [`Audit`] when present in the request
+/// extensions.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Principal(pub Arc<str>);
+
+/// One request in the audit log.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditRecord {
+    /// Position in the log, starting at zero.
+    pub sequence: u64,
+    /// Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    pub method: String,
+    pub url: String,
+    /// SHA-256 of the request body, hex encoded, or empty if the body was
+    /// [streamed](crate::Streaming).
+    pub body: String,
+    pub principal: Option<String>,
+    /// `hash` of the previous record.
+    pub previous: String,
+    /// SHA-256 of every other field, hex encoded.
+    pub hash: String,
+}
+
+impl AuditRecord {
+    fn digest(&self) -> String {
+        let fields = (
+            self.sequence,
+            self.timestamp,
+            &self.method,
+            &self.url,
+            &self.body,
+            &self.principal,
+            &self.previous,
+        );
+        let json = serde_json::to_vec(&fields).expect("plain fields always serialize");
+        hex(&Sha256::digest(json))
+    }
+}
+
+/// Middleware appending every request to an audit log.
+///
+/// Request bodies are buffered to be hashed, except [streaming](Streaming) ones. Records are
+/// written on Tokio's blocking thread pool, so `writer` may be a file. If a record can't be
+/// written, the request fails with [`Error::Io`] without being sent. [Dry runs](DryRun) aren't
+/// recorded, as they're never sent.
+pub struct Audit {
+    log: Arc<Mutex<Log>>,
+}
+
+struct Log {
+    writer: Box<dyn Write + Send>,
+    sequence: u64,
+    previous: String,
+}
+
+impl Audit {
+    /// Start a new log written to `writer`, one record per line.
+    pub fn new(writer: impl Write + Send + 'static) -> Self {
+        Self {
+            log: Arc::new(Mutex::new(Log {
+                writer: Box::new(writer),
+                sequence: 0,
+                previous: GENESIS.to_owned(),
+            })),
+        }
+    }
+
+    /// Continue the chain of an existing log whose last record is `last`.
+    pub fn resume(self, last: &AuditRecord) -> Self {
+        {
+            let mut log = self.log.lock().unwrap_or_else(PoisonError::into_inner);
+            log.sequence = last.sequence + 1;
+            log.previous = last.hash.clone();
+        }
+        self
+    }
+}
+
+impl Log {
+    /// Chain `record` to the log and write it out.
+    fn append(&mut self, mut record: AuditRecord) -> io::Result<()> {
+        record.sequence = self.sequence;
+        record.previous = self.previous.clone();
+        record.hash = record.digest();
+
+        let mut line = serde_json::to_vec(&record)?;
+        line.push(b'\n');
+        self.writer.write_all(&line)?;
+        self.writer.flush()?;
+        self.sequence += 1;
+        self.previous = record.hash;
+        Ok(())
+    }
+}
+
+impl Debug for Audit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let log = self.log.lock().unwrap_or_else(PoisonError::into_inner);
+        f.debug_struct("Audit")
+            .field("sequence", &log.sequence)
+            .field("previous", &log.previous)
+            .finish_non_exhaustive()
+    }
+}
+
+#[async_trait]
+impl Middleware for Audit {
+    async fn handle(&self, request: &mut Request, next: Next<'_>) -> Result<Response> {
+        if request.extensions().get::<DryRun>().is_some() {
+            return next.run(request).await;
+        }
+        // A streaming body may only end after the response, so it can't be hashed up front.
+        let body_hash = if request.extensions().get::<Streaming>().is_some() {
+            String::new()
+        } else {
+            let body = request.into_bytes().await?;
+            let body_hash = hex(&Sha256::digest(&body));
+            request.replace_body(body);
+            body_hash
+        };
+
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as u64;
+        let principal = request
+            .extensions()
+            .get::<Principal>()
+            .map(|principal| principal.0.to_string());
+
+        let record = AuditRecord {
+            sequence: 0,
+            timestamp,
+            method: request.method().to_string(),
+            url: request.uri().to_string(),
+            body: body_hash,
+            principal,
+            previous: String::new(),
+            hash: String::new(),
+        };
+        let log = self.log.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut log = log.lock().unwrap_or_else(PoisonError::into_inner);
+            log.append(record)
+        })
+        .await
+        .map_err(|error| Error::Io(io::Error::other(error)))?
+        .map_err(Error::Io)?;
+
+        next.run(request).await
+    }
+}
+
+/// Check that `records` form an unbroken chain from the start of a log.
+///
+/// On failure, returns the position of the first record that was altered or is out of place.
+pub fn verify(records: impl IntoIterator<Item = AuditRecord>) -> std::result::Result<(), u64> {
+    let mut previous = GENESIS.to_owned();
+    for (sequence, record) in (0..).zip(records) {
+        if record.sequence != sequence
+            || record.previous != previous
+            || record.hash != record.digest()
+        {
+            return Err(sequence);
+        }
+        previous = record.hash;
+    }
+    Ok(())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{verify, Audit, AuditRecord};
+    use crate::middleware::Capture;
+    use crate::{Client, Error, Middleware, Next};
+    use http_kit::{Method, Request};
+    use std::io::Write;
+    use std::sync::{Arc, Mutex, PoisonError};
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+                .write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn chain() {
+        let log = Shared::default();
+        let audit = Audit::new(log.clone());
+        for path in ["/a", "/b", "/c"] {
+            let mut request = Request::new(Method::POST, path);
+            let capture = Capture::default();
+            audit
+                .handle(&mut request, Next::new(&[], &capture))
+                .await
+                .unwrap();
+        }
+
+        let log = log.0.lock().unwrap().clone();
+        let mut records: Vec<AuditRecord> = log
+            .split(|byte| *byte == b'\n')
+            .filter(|line| !line.is_empty())
+            .map(|line| serde_json::from_slice(line).unwrap())
+            .collect();
+        assert_eq!(records.len(), 3);
+        assert_eq!(verify(records.clone()), Ok(()));
+
+        records[1].url = "/evil".to_owned();
+        assert_eq!(verify(records.clone()), Err(1));
+        records.remove(1);
+        assert_eq!(verify(records), Err(1));
+    }
+
+    struct Broken;
+
+    impl Write for Broken {
+        fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::ErrorKind::Other.into())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn unwritable_records_stop_the_request() {
+        let audit = Audit::new(Broken);
+        let mut request = Request::new(Method::POST, "/a");
+        let capture = Capture::default();
+        let result = audit.handle(&mut request, Next::new(&[], &capture)).await;
+        assert!(matches!(result, Err(Error::Io(_))));
+        assert!(capture.into_request().is_none());
+    }
+
+    #[tokio::test]
+    async fn dry_run_isnt_recorded() {
+        let log = Shared::default();
+        let client = Client::new().middleware(Audit::new(log.clone()));
+        client.post("http://example.com/").dry_run().await.unwrap();
+        assert!(log.0.lock().unwrap().is_empty());
+    }
+}
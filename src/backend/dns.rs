@@ -0,0 +1,272 @@
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+use std::future::Future;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use hyper::client::connect::dns::Name;
+use hyper::client::HttpConnector;
+use hyper::service::Service;
+
+use super::HyperBackend;
+
+/// An asynchronous hostname lookup, e.g. one backed by trust-dns.
+#[async_trait]
+pub trait Resolve: Send + Sync + 'static {
+    async fn resolve(&self, host: &str) -> io::Result<Vec<IpAddr>>;
+}
+
+/// The operating system's resolver (`getaddrinfo`).
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemResolver;
+
+#[async_trait]
+impl Resolve for SystemResolver {
+    async fn resolve(&self, host: &str) -> io::Result<Vec<IpAddr>> {
+        let addrs = tokio::net::lookup_host((host, 0)).await?;
+        Ok(addrs.map(|addr| addr.ip()).collect())
+    }
+}
+
+type Cache = HashMap<String, (Instant, Vec<IpAddr>)>;
+
+/// How many hostnames the cache of a [`Resolver`] holds at most.
+const CACHE_CAPACITY: usize = 1024;
+
+/// Hostname resolution for [`HyperBackend`].
+///
+/// Lookups first consult the static overrides, then the cache (if a TTL is set) and finally
+/// the underlying [`Resolve`] implementation, which defaults to [`SystemResolver`].
+/// All resolved addresses are handed to the connector, so dual-stack hosts still get
+/// happy-eyeballs connection attempts. Hostnames are matched case-insensitively.
+#[derive(Clone)]
+pub struct Resolver {
+    overrides: Arc<HashMap<String, Vec<IpAddr>>>,
+    resolver: Arc<dyn Resolve>,
+    ttl: Option<Duration>,
+    cache: Arc<Mutex<Cache>>,
+}
+
+impl Resolver {
+    pub fn new() -> Self {
+        Self::custom(SystemResolver)
+    }
+
+    /// Resolve hostnames through `resolver` instead of the system resolver.
+    pub fn custom(resolver: impl Resolve) -> Self {
+        Self {
+            overrides: Arc::default(),
+            resolver: Arc::new(resolver),
+            ttl: None,
+            cache: Arc::default(),
+        }
+    }
+
+    /// Always resolve `host` to `addr`, bypassing any lookup. Can be called repeatedly to
+    /// give a host several addresses.
+    pub fn resolve(mut self, host: impl Into<String>, addr: impl Into<IpAddr>) -> Self {
+        let host = host.into().to_ascii_lowercase();
+        Arc::make_mut(&mut self.overrides)
+            .entry(host)
+            .or_default()
+            .push(addr.into());
+        self
+    }
+
+    /// Cache successful lookups for `ttl`.
+    ///
+    /// [`Resolve`] only returns addresses, so every entry is kept for `ttl` whatever the TTL
+    /// of its DNS records; keep it short enough for the records to be refreshed in time. At most
+    /// 1024 hostnames are cached, expired ones and then the oldest making room for new ones.
+    pub fn cache(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+
+    async fn lookup(&self, host: &str) -> io::Result<Vec<IpAddr>> {
+        let host = host.to_ascii_lowercase();
+        if let Some(addrs) = self.overrides.get(&host) {
+            return Ok(addrs.clone());
+        }
+        if let Some(addrs) = self.cached(&host) {
+            return Ok(addrs);
+        }
+
+        let addrs = self.resolver.resolve(&host).await?;
+        if let Some(ttl) = self.ttl {
+            let mut entries = self.entries();
+            if entries.len() >= CACHE_CAPACITY && !entries.contains_key(&host) {
+                entries.retain(|_, (resolved_at, _)| resolved_at.elapsed() < ttl);
+            }
+            if entries.len() >= CACHE_CAPACITY && !entries.contains_key(&host) {
+                let oldest = entries
+                    .iter()
+                    .min_by_key(|(_, (resolved_at, _))| *resolved_at)
+                    .map(|(host, _)| host.clone());
+                if let Some(oldest) = oldest {
+                    entries.remove(&oldest);
+                }
+            }
+            entries.insert(host, (Instant::now(), addrs.clone()));
+        }
+        Ok(addrs)
+    }
+
+    fn cached(&self, host: &str) -> Option<Vec<IpAddr>> {
+        let ttl = self.ttl?;
+        let mut entries = self.entries();
+        match entries.get(host) {
+            Some((resolved_at, addrs)) if resolved_at.elapsed() < ttl => Some(addrs.clone()),
+            Some(_) => {
+                entries.remove(host);
+                None
+            }
+            None => None,
+        }
+    }
+
+    fn entries(&self) -> MutexGuard<'_, Cache> {
+        self.cache.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl Default for Resolver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Debug for Resolver {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Resolver")
+            .field("overrides", &self.overrides)
+            .field("ttl", &self.ttl)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Service<Name> for Resolver {
+    type Response = std::vec::IntoIter<SocketAddr>;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<Self::Response>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, name: Name) -> Self::Future {
+        let resolver = self.clone();
+        Box::pin(async move {
+            let addrs = resolver.lookup(name.as_str()).await?;
+            // The connector fills in the port from the request URI.
+            let addrs: Vec<SocketAddr> =
+                addrs.into_iter().map(|ip| SocketAddr::new(ip, 0)).collect();
+            Ok(addrs.into_iter())
+        })
+    }
+}
+
+impl HyperBackend<HttpConnector<Resolver>> {
+    /// Create a backend that resolves hostnames through `resolver`.
+    pub fn with_resolver(resolver: Resolver) -> Self {
+        Self::with_connector(HttpConnector::new_with_resolver(resolver))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Resolve, Resolver, CACHE_CAPACITY};
+    use async_trait::async_trait;
+    use std::io;
+    use std::net::{IpAddr, Ipv4Addr};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// Resolves every host to `127.0.0.1`, counting the lookups.
+    struct Counting(Arc<AtomicUsize>);
+
+    #[async_trait]
+    impl Resolve for Counting {
+        async fn resolve(&self, _host: &str) -> io::Result<Vec<IpAddr>> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Ok(vec![Ipv4Addr::LOCALHOST.into()])
+        }
+    }
+
+    fn counting() -> (Resolver, Arc<AtomicUsize>) {
+        let counter: Arc<AtomicUsize> = Arc::default();
+        (Resolver::custom(Counting(Arc::clone(&counter))), counter)
+    }
+
+    #[tokio::test]
+    async fn overrides() {
+        let (resolver, lookups) = counting();
+        let resolver = resolver
+            .resolve("API.example.com", [10, 0, 0, 1])
+            .resolve("api.example.com", [10, 0, 0, 2]);
+        let expected: Vec<IpAddr> = vec![[10, 0, 0, 1].into(), [10, 0, 0, 2].into()];
+        assert_eq!(resolver.lookup("api.example.com").await.unwrap(), expected);
+        assert_eq!(resolver.lookup("Api.Example.Com").await.unwrap(), expected);
+        assert_eq!(lookups.load(Ordering::SeqCst), 0);
+
+        resolver.lookup("other.example.com").await.unwrap();
+        assert_eq!(lookups.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn cache() {
+        let (resolver, lookups) = counting();
+        resolver.lookup("example.com").await.unwrap();
+        resolver.lookup("example.com").await.unwrap();
+        assert_eq!(
+            lookups.load(Ordering::SeqCst),
+            2,
+            "nothing is cached without a ttl"
+        );
+
+        let (resolver, lookups) = counting();
+        let resolver = resolver.cache(Duration::from_secs(60));
+        resolver.lookup("example.com").await.unwrap();
+        resolver.lookup("EXAMPLE.com").await.unwrap();
+        assert_eq!(lookups.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn cache_expiry() {
+        let (resolver, lookups) = counting();
+        let resolver = resolver.cache(Duration::from_millis(20));
+        resolver.lookup("example.com").await.unwrap();
+        tokio::time::sleep(Duration::from_millis(40)).await;
+        resolver.lookup("example.com").await.unwrap();
+        assert_eq!(lookups.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn cache_is_bounded() {
+        let (resolver, lookups) = counting();
+        let resolver = resolver.cache(Duration::from_secs(60));
+        for index in 0..=CACHE_CAPACITY {
+            resolver
+                .lookup(&format!("{index}.example.com"))
+                .await
+                .unwrap();
+        }
+        assert_eq!(resolver.entries().len(), CACHE_CAPACITY);
+
+        // The oldest entry made room for the newest.
+        let before = lookups.load(Ordering::SeqCst);
+        resolver
+            .lookup(&format!("{CACHE_CAPACITY}.example.com"))
+            .await
+            .unwrap();
+        assert_eq!(lookups.load(Ordering::SeqCst), before);
+        resolver.lookup("0.example.com").await.unwrap();
+        assert_eq!(lookups.load(Ordering::SeqCst), before + 1);
+    }
+}
@@ -1,25 +1,125 @@
+use std::any::TypeId;
+use std::io;
 use std::mem::replace;
 
 use async_trait::async_trait;
-use http_kit::{Endpoint, Method, Request, Response};
+use http_kit::header::{self, HeaderMap};
+use http_kit::{Endpoint, Method, Request, Response, StatusCode};
+use hyper::client::connect::Connect;
 use hyper::client::HttpConnector;
 use hyper::http;
 
 use crate::ClientBackend;
 
-#[derive(Debug, Default)]
-pub struct HyperBackend {
-    client: hyper::Client<HttpConnector, hyper::Body>,
+/// A transport the hyper backend can open connections with.
+///
+/// Every hyper connector qualifies: [`HttpConnector`], [`UnixConnector`](super::UnixConnector),
+/// an in-memory duplex for tests, a Tor or SOCKS dialer, and so on.
+pub trait Connector: Connect + Clone + Send + Sync + 'static {}
+
+impl<C: Connect + Clone + Send + Sync + 'static> Connector for C {}
+
+/// How the end of a response body is known, attached to the extensions of every response
+/// of [`HyperBackend`], which speaks HTTP/1 only.
+///
+/// Every kind but [`Framing::Close`] is checked by the protocol: a body cut short by the
+/// connection fails with an error. A close-delimited body, however, ends whenever the peer
+/// closes the connection, so a truncated one looks complete.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Framing {
+    /// The response has no body, because of its status or the request method.
+    Empty,
+    /// The body is as long as its `Content-Length`.
+    Length,
+    /// The body ends with the final chunk of `Transfer-Encoding: chunked`.
+    Chunked,
+    /// The body ends when the peer closes the connection.
+    Close,
+}
+
+impl Framing {
+    fn of(method: &Method, status: StatusCode, headers: &HeaderMap) -> Self {
+        let chunked = headers
+            .get_all(header::TRANSFER_ENCODING)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .last()
+            .is_some_and(|coding| coding.trim().eq_ignore_ascii_case("chunked"));
+
+        if method == Method::HEAD
+            || status.is_informational()
+            || status == StatusCode::NO_CONTENT
+            || status == StatusCode::NOT_MODIFIED
+        {
+            Framing::Empty
+        } else if chunked {
+            Framing::Chunked
+        } else if headers.contains_key(header::CONTENT_LENGTH) {
+            Framing::Length
+        } else {
+            Framing::Close
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HyperBackend<C = HttpConnector> {
+    client: hyper::Client<C, hyper::Body>,
+    strict: bool,
+}
+
+impl HyperBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Default for HyperBackend {
+    fn default() -> Self {
+        Self {
+            client: hyper::Client::default(),
+            strict: false,
+        }
+    }
+}
+
+impl<C: Connector> HyperBackend<C> {
+    /// Create a backend that opens its connections with `connector`.
+    pub fn with_connector(connector: C) -> Self {
+        Self {
+            client: hyper::Client::builder().build(connector),
+            strict: false,
+        }
+    }
+
+    /// Fail responses whose body is delimited by the connection closing, see [`Framing`].
+    pub fn strict_framing(mut self) -> Self {
+        self.strict = true;
+        self
+    }
 }
 
 #[async_trait]
-impl Endpoint for HyperBackend {
+impl<C: Connector> Endpoint for HyperBackend<C> {
     async fn call_endpoint(&self, request: &mut Request) -> http_kit::Result<Response> {
         let request: http::Request<http_kit::Body> =
             replace(request, Request::new(Method::GET, "/")).into();
+        let method = request.method().clone();
         let request = request.map(|body| hyper::Body::wrap_stream(body));
 
-        let response = self.client.request(request).await?;
+        let mut response = self.client.request(request).await?;
+
+        let framing = Framing::of(&method, response.status(), response.headers());
+        if self.strict && framing == Framing::Close {
+            let error = io::Error::new(
+                io::ErrorKind::InvalidData,
+                "response body is delimited by the connection closing",
+            );
+            return Err(error.into());
+        }
+        response.extensions_mut().insert(framing);
 
         let response = response
             .map(|body| http_kit::Body::from_stream(body))
@@ -29,4 +129,113 @@ impl Endpoint for HyperBackend {
     }
 }
 
-impl ClientBackend for HyperBackend {}
+impl<C: Connector> ClientBackend for HyperBackend<C> {
+    fn system_connector(&self) -> bool {
+        TypeId::of::<C>() == TypeId::of::<HttpConnector>()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Framing, HyperBackend};
+    use http_kit::header::{self, HeaderMap, HeaderValue};
+    use http_kit::{Endpoint, Method, Request, StatusCode};
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    fn framing(
+        method: Method,
+        status: u16,
+        headers: &[(header::HeaderName, &'static str)],
+    ) -> Framing {
+        let mut map = HeaderMap::new();
+        for (name, value) in headers {
+            map.append(name.clone(), HeaderValue::from_static(value));
+        }
+        Framing::of(&method, StatusCode::from_u16(status).unwrap(), &map)
+    }
+
+    #[test]
+    fn of() {
+        let chunked = [(header::TRANSFER_ENCODING, "chunked")];
+        let length = [(header::CONTENT_LENGTH, "5")];
+        assert_eq!(framing(Method::HEAD, 200, &length), Framing::Empty);
+        assert_eq!(framing(Method::GET, 204, &chunked), Framing::Empty);
+        assert_eq!(framing(Method::GET, 304, &length), Framing::Empty);
+        assert_eq!(framing(Method::GET, 200, &chunked), Framing::Chunked);
+        assert_eq!(
+            framing(
+                Method::GET,
+                200,
+                &[(header::TRANSFER_ENCODING, "gzip, Chunked")]
+            ),
+            Framing::Chunked
+        );
+        // Chunked has to be the final coding, or the body runs until the connection closes.
+        assert_eq!(
+            framing(
+                Method::GET,
+                200,
+                &[(header::TRANSFER_ENCODING, "chunked, gzip")]
+            ),
+            Framing::Close
+        );
+        assert_eq!(
+            framing(Method::GET, 200, &[chunked[0].clone(), length[0].clone()]),
+            Framing::Chunked
+        );
+        assert_eq!(framing(Method::GET, 200, &length), Framing::Length);
+        assert_eq!(framing(Method::GET, 200, &[]), Framing::Close);
+    }
+
+    /// Answer a single request with the raw `response`, then close the connection.
+    fn serve(response: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                match stream.read(&mut buf) {
+                    Ok(0) | Err(_) => return,
+                    Ok(read) => request.extend_from_slice(&buf[..read]),
+                }
+            }
+            let _ = stream.write_all(response.as_bytes());
+        });
+        format!("http://{addr}/")
+    }
+
+    async fn get(backend: &HyperBackend, response: &'static str) -> http_kit::Result<Framing> {
+        let mut request = Request::new(Method::GET, serve(response).as_str());
+        let mut response = backend.call_endpoint(&mut request).await?;
+        let framing = *response.extensions().get::<Framing>().unwrap();
+        response.into_bytes().await?;
+        Ok(framing)
+    }
+
+    #[tokio::test]
+    async fn strict_framing() {
+        let close = "HTTP/1.1 200 OK\r\nconnection: close\r\n\r\nhello";
+        let length = "HTTP/1.1 200 OK\r\ncontent-length: 5\r\n\r\nhello";
+        let duplicate = "HTTP/1.1 200 OK\r\ncontent-length: 5\r\ncontent-length: 5\r\n\r\nhello";
+        let chunked =
+            "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\n\r\n5\r\nhello\r\n0\r\n\r\n";
+
+        let lenient = HyperBackend::new();
+        assert_eq!(get(&lenient, close).await.unwrap(), Framing::Close);
+
+        let strict = HyperBackend::new().strict_framing();
+        assert!(get(&strict, close).await.is_err());
+        assert_eq!(get(&strict, length).await.unwrap(), Framing::Length);
+        assert_eq!(get(&strict, duplicate).await.unwrap(), Framing::Length);
+        assert_eq!(get(&strict, chunked).await.unwrap(), Framing::Chunked);
+    }
+
+    #[tokio::test]
+    async fn conflicting_content_length() {
+        let conflicting = "HTTP/1.1 200 OK\r\ncontent-length: 5\r\ncontent-length: 6\r\n\r\nhello!";
+        assert!(get(&HyperBackend::new(), conflicting).await.is_err());
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll};
+use std::time::Instant;
+
+use crate::uri::origin;
+
+use hyper::client::connect::{Connected, Connection};
+use hyper::service::Service;
+use hyper::Uri;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// A connector counting the bytes every connection of the wrapped connector transfers.
+///
+/// Bytes are counted on the wire, so they include headers and framing, per origin:
+/// scheme, host and port of the URI a connection was opened for.
+#[derive(Debug, Clone)]
+pub struct Metered<C> {
+    inner: C,
+    traffic: Arc<Traffic>,
+}
+
+impl<C> Metered<C> {
+    pub fn new(inner: C) -> Self {
+        Self {
+            inner,
+            traffic: Arc::default(),
+        }
+    }
+
+    /// Counters per origin, shared with the connector once it's handed to a backend.
+    pub fn traffic(&self) -> Arc<Traffic> {
+        self.traffic.clone()
+    }
+}
+
+/// Bytes transferred per origin.
+#[derive(Debug, Default)]
+pub struct Traffic {
+    origins: Mutex<BTreeMap<String, Arc<Counters>>>,
+}
+
+#[derive(Debug)]
+struct Counters {
+    sent: AtomicU64,
+    received: AtomicU64,
+    connections: AtomicU64,
+}
+
+/// The traffic of one origin at the time of a [`Traffic::snapshot`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct OriginTraffic {
+    pub origin: String,
+    pub sent: u64,
+    pub received: u64,
+    /// Connections currently open.
+    pub connections: u64,
+    /// When the snapshot was taken.
+    pub taken: Instant,
+}
+
+impl OriginTraffic {
+    /// Bytes per second sent between `earlier`, a snapshot of the same origin, and this one.
+    pub fn send_rate(&self, earlier: &OriginTraffic) -> f64 {
+        self.rate(self.sent.saturating_sub(earlier.sent), earlier)
+    }
+
+    /// Bytes per second received between `earlier`, a snapshot of the same origin, and this
+    /// one.
+    pub fn receive_rate(&self, earlier: &OriginTraffic) -> f64 {
+        self.rate(self.received.saturating_sub(earlier.received), earlier)
+    }
+
+    fn rate(&self, bytes: u64, earlier: &OriginTraffic) -> f64 {
+        let elapsed = self.taken.saturating_duration_since(earlier.taken);
+        if elapsed.is_zero() {
+            0.0
+        } else {
+            bytes as f64 / elapsed.as_secs_f64()
+        }
+    }
+}
+
+impl Traffic {
+    fn origins(&self) -> MutexGuard<'_, BTreeMap<String, Arc<Counters>>> {
+        self.origins.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn counters(&self, uri: &Uri) -> Arc<Counters> {
+        self.origins()
+            .entry(origin(uri))
+            .or_insert_with(|| {
+                Arc::new(Counters {
+                    sent: AtomicU64::new(0),
+                    received: AtomicU64::new(0),
+                    connections: AtomicU64::new(0),
+                })
+            })
+            .clone()
+    }
+
+    /// The traffic of every origin, ordered by origin.
+    ///
+    /// Taking a snapshot changes nothing, so several consumers can take their own. Transfer
+    /// rates are derived from two snapshots, see [`OriginTraffic::send_rate`]; keep the
+    /// previous one around to compute them.
+    pub fn snapshot(&self) -> Vec<OriginTraffic> {
+        let taken = Instant::now();
+        self.origins()
+            .iter()
+            .map(|(origin, counters)| OriginTraffic {
+                origin: origin.clone(),
+                sent: counters.sent.load(Ordering::Relaxed),
+                received: counters.received.load(Ordering::Relaxed),
+                connections: counters.connections.load(Ordering::Relaxed),
+                taken,
+            })
+            .collect()
+    }
+}
+
+impl<C> Service<Uri> for Metered<C>
+where
+    C: Service<Uri>,
+    C::Future: Send + 'static,
+{
+    type Response = MeteredStream<C::Response>;
+    type Error = C::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, C::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), C::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let counters = self.traffic.counters(&uri);
+        let connecting = self.inner.call(uri);
+        Box::pin(async move {
+            let stream = connecting.await?;
+            counters.connections.fetch_add(1, Ordering::Relaxed);
+            Ok(MeteredStream { stream, counters })
+        })
+    }
+}
+
+/// A connection opened by [`Metered`].
+#[derive(Debug)]
+pub struct MeteredStream<T> {
+    stream: T,
+    counters: Arc<Counters>,
+}
+
+impl<T> Drop for MeteredStream<T> {
+    fn drop(&mut self) {
+        self.counters.connections.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl<T: Connection> Connection for MeteredStream<T> {
+    fn connected(&self) -> Connected {
+        self.stream.connected()
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for MeteredStream<T> {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut self.stream).poll_read(cx, buf);
+        let read = buf.filled().len() - before;
+        self.counters
+            .received
+            .fetch_add(read as u64, Ordering::Relaxed);
+        poll
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for MeteredStream<T> {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.stream).poll_write(cx, buf);
+        if let Poll::Ready(Ok(written)) = poll {
+            self.counters
+                .sent
+                .fetch_add(written as u64, Ordering::Relaxed);
+        }
+        poll
+    }
+
+    fn poll_write_vectored(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[io::IoSlice<'_>],
+    ) -> Poll<io::Result<usize>> {
+        let poll = Pin::new(&mut self.stream).poll_write_vectored(cx, bufs);
+        if let Poll::Ready(Ok(written)) = poll {
+            self.counters
+                .sent
+                .fetch_add(written as u64, Ordering::Relaxed);
+        }
+        poll
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.stream.is_write_vectored()
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stream).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Traffic;
+    use hyper::Uri;
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    #[test]
+    fn snapshots() {
+        let traffic = Traffic::default();
+        let counters = traffic.counters(&Uri::from_static("http://Example.com/a"));
+        assert!(std::sync::Arc::ptr_eq(
+            &counters,
+            &traffic.counters(&Uri::from_static("http://example.com:80/b"))
+        ));
+        counters.sent.fetch_add(100, Ordering::Relaxed);
+        let first = traffic.snapshot();
+
+        counters.sent.fetch_add(50, Ordering::Relaxed);
+        counters.received.fetch_add(400, Ordering::Relaxed);
+        // Snapshots don't affect each other.
+        assert_eq!(traffic.snapshot()[0].sent, 150);
+        let mut second = traffic.snapshot().remove(0);
+        second.taken = first[0].taken + Duration::from_secs(2);
+
+        assert_eq!(first[0].origin, "http://example.com:80");
+        assert_eq!(first[0].sent, 100);
+        assert_eq!(second.send_rate(&first[0]), 25.0);
+        assert_eq!(second.receive_rate(&first[0]), 200.0);
+        assert_eq!(first[0].send_rate(&first[0]), 0.0);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn counts_connections() {
+        use crate::backend::{HyperBackend, Metered};
+        use crate::test_util::test_server;
+        use crate::Client;
+        use hyper::client::HttpConnector;
+        use hyper::http::{Method, Response};
+
+        let server = test_server()
+            .route(Method::GET, "/", |_| Response::new("ok".into()))
+            .start();
+        let connector = Metered::new(HttpConnector::new());
+        let traffic = connector.traffic();
+        let client = Client::with_backend(HyperBackend::with_connector(connector));
+        let mut response = client.get(server.url("/").as_str()).await.unwrap();
+        response.into_bytes().await.unwrap();
+
+        let snapshot = traffic.snapshot();
+        assert_eq!(snapshot.len(), 1);
+        assert_eq!(snapshot[0].origin, format!("http://{}", server.addr()));
+        assert!(snapshot[0].sent > 0 && snapshot[0].received > 0);
+    }
+}
@@ -0,0 +1,259 @@
+//! A backend answering from a declarative scenario instead of the network.
+
+use crate::ClientBackend;
+use async_trait::async_trait;
+use bytes::Bytes;
+use http_kit::{Body, Endpoint, Request, Response};
+use hyper::http;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Rules describing how a [`MockBackend`] answers requests.
+///
+/// Scenarios are meant to be written by hand, usually as TOML:
+///
+/// ```toml
+/// [[rule]]
+/// method = "GET"
+/// path = "/users/*"
+/// expect = 2
+///
+/// [[rule.responses]]
+/// status = 200
+/// json = { id = 1, name = "Alice" }
+/// delay_ms = 50
+///
+/// [[rule.responses]]
+/// fail = "connection reset"
+/// ```
+///
+/// The first rule matching a request answers it. A rule replies with its responses in order
+/// and keeps repeating the last one. Any other serde format works too, by deserializing a
+/// `Scenario` directly.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Scenario {
+    #[serde(default, rename = "rule")]
+    rules: Vec<Rule>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Rule {
+    /// Matches any method if missing.
+    method: Option<String>,
+    /// An exact path, or a prefix when it ends with `*`.
+    path: String,
+    /// Headers the request must carry with exactly these values.
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    /// Text the request body must contain.
+    body_contains: Option<String>,
+    /// How many times the rule must match for [`MockBackend::verify`] to pass.
+    expect: Option<usize>,
+    responses: Vec<Reply>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Reply {
+    #[serde(default = "ok")]
+    status: u16,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    #[serde(default)]
+    body: String,
+    /// Sent as the body with a JSON `Content-Type`, instead of `body`.
+    json: Option<serde_json::Value>,
+    #[serde(default)]
+    delay_ms: u64,
+    /// Fail the request with this message instead of responding.
+    fail: Option<String>,
+}
+
+fn ok() -> u16 {
+    200
+}
+
+impl Scenario {
+    pub fn from_toml(scenario: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(scenario)
+    }
+
+    /// Read a TOML scenario from `path`.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        let scenario = std::fs::read_to_string(path)?;
+        Self::from_toml(&scenario)
+            .map_err(|error| io::Error::new(io::ErrorKind::InvalidData, error))
+    }
+}
+
+impl Rule {
+    fn matches(&self, request: &Request, body: &[u8]) -> bool {
+        let path = request.uri().path();
+        let path = match self.path.strip_suffix('*') {
+            Some(prefix) => path.starts_with(prefix),
+            None => path == self.path,
+        };
+        let method = self
+            .method
+            .as_ref()
+            .is_none_or(|method| method.eq_ignore_ascii_case(request.method().as_str()));
+        let headers = self.headers.iter().all(|(name, value)| {
+            request
+                .headers()
+                .get_all(name.as_str())
+                .iter()
+                .any(|actual| actual.as_bytes() == value.as_bytes())
+        });
+        let body = self
+            .body_contains
+            .as_ref()
+            .is_none_or(|needle| String::from_utf8_lossy(body).contains(needle.as_str()));
+        path && method && headers && body
+    }
+}
+
+impl Reply {
+    fn response(&self) -> http_kit::Result<Response> {
+        let mut response = http::Response::builder().status(self.status);
+        let body = match &self.json {
+            Some(json) => {
+                if !self
+                    .headers
+                    .keys()
+                    .any(|name| name.eq_ignore_ascii_case("content-type"))
+                {
+                    response = response.header(http::header::CONTENT_TYPE, "application/json");
+                }
+                Bytes::from(json.to_string())
+            }
+            None => Bytes::from(self.body.clone()),
+        };
+        for (name, value) in &self.headers {
+            response = response.header(name.as_str(), value.as_str());
+        }
+        Ok(response.body(Body::from(body))?.into())
+    }
+}
+
+/// A [`ClientBackend`] that answers requests according to a [`Scenario`].
+///
+/// Requests no rule matches fail, so a test notices calls the scenario didn't anticipate.
+#[derive(Debug)]
+pub struct MockBackend {
+    rules: Vec<Rule>,
+    calls: Vec<AtomicUsize>,
+}
+
+impl MockBackend {
+    pub fn new(scenario: Scenario) -> Self {
+        Self {
+            calls: scenario.rules.iter().map(|_| AtomicUsize::new(0)).collect(),
+            rules: scenario.rules,
+        }
+    }
+
+    /// Check that every rule with an `expect` count matched exactly that many times.
+    pub fn verify(&self) -> Result<(), String> {
+        let unmet: Vec<String> = self
+            .rules
+            .iter()
+            .zip(&self.calls)
+            .filter_map(|(rule, calls)| {
+                let expected = rule.expect?;
+                let calls = calls.load(Ordering::Relaxed);
+                (calls != expected).then(|| {
+                    let method = rule.method.as_deref().unwrap_or("*");
+                    format!(
+                        "{method} {}: expected {expected} calls, got {calls}",
+                        rule.path
+                    )
+                })
+            })
+            .collect();
+        if unmet.is_empty() {
+            Ok(())
+        } else {
+            Err(unmet.join("\n"))
+        }
+    }
+}
+
+#[async_trait]
+impl Endpoint for MockBackend {
+    async fn call_endpoint(&self, request: &mut Request) -> http_kit::Result<Response> {
+        let body = request.into_bytes().await?;
+        let index = self
+            .rules
+            .iter()
+            .position(|rule| rule.matches(request, &body))
+            .ok_or_else(|| {
+                let message = format!("no rule matches {} {}", request.method(), request.uri());
+                io::Error::new(io::ErrorKind::NotFound, message)
+            })?;
+
+        let rule = &self.rules[index];
+        let call = self.calls[index].fetch_add(1, Ordering::Relaxed);
+        let reply = rule
+            .responses
+            .get(call)
+            .or(rule.responses.last())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "rule without responses"))?;
+
+        if reply.delay_ms > 0 {
+            tokio::time::sleep(Duration::from_millis(reply.delay_ms)).await;
+        }
+        if let Some(message) = &reply.fail {
+            return Err(io::Error::new(io::ErrorKind::ConnectionReset, message.clone()).into());
+        }
+        reply.response()
+    }
+}
+
+impl ClientBackend for MockBackend {}
+
+#[cfg(test)]
+mod test {
+    use super::{MockBackend, Scenario};
+    use crate::Client;
+
+    const SCENARIO: &str = r#"
+        [[rule]]
+        method = "GET"
+        path = "/users/*"
+        expect = 3
+
+        [[rule.responses]]
+        json = { id = 1 }
+
+        [[rule.responses]]
+        status = 503
+        body = "busy"
+
+        [[rule]]
+        path = "/flaky"
+
+        [[rule.responses]]
+        fail = "connection reset"
+    "#;
+
+    #[tokio::test]
+    async fn scenario() {
+        let scenario = Scenario::from_toml(SCENARIO).unwrap();
+        let client = Client::with_backend(MockBackend::new(scenario));
+
+        let mut response = client.get("http://api.test/users/1").await.unwrap();
+        assert_eq!(response.status(), 200);
+        assert_eq!(response.into_string().await.unwrap(), r#"{"id":1}"#);
+        for _ in 0..2 {
+            let response = client.get("http://api.test/users/2").await.unwrap();
+            assert_eq!(response.status(), 503);
+        }
+
+        assert!(client.get("http://api.test/flaky").await.is_err());
+        assert!(client.post("http://api.test/users/1").await.is_err());
+        assert_eq!(client.backend.verify(), Ok(()));
+    }
+}
@@ -1,4 +1,28 @@
+mod dns;
 mod hyper;
-pub use hyper::HyperBackend;
+#[cfg(feature = "metrics")]
+mod metered;
+#[cfg(feature = "mock")]
+mod mock;
+pub use dns::{Resolve, Resolver, SystemResolver};
+pub use hyper::{Connector, Framing, HyperBackend};
+#[cfg(feature = "metrics")]
+pub use metered::{Metered, MeteredStream, OriginTraffic, Traffic};
+#[cfg(feature = "mock")]
+pub use mock::{MockBackend, Scenario};
 
-pub trait ClientBackend: http_kit::Endpoint + Default {}
+#[cfg(unix)]
+mod unix;
+#[cfg(unix)]
+pub use unix::{Routed, UnixConnector, UnixRouter, UnixStream};
+
+pub trait ClientBackend: http_kit::Endpoint + Send + Sync {
+    /// Whether hosts are resolved by the system resolver and connected to over plain TCP, so
+    /// that [`Client::diagnose`](crate::Client::diagnose) can check those stages on its own.
+    ///
+    /// Backends with a custom connector, such as a [`Resolver`] or a Unix socket, keep the
+    /// default of `false`; only their HTTP stage is checked.
+    fn system_connector(&self) -> bool {
+        false
+    }
+}
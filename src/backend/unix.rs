@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::future::Future;
+use std::io;
+use std::path::Path;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+
+use hyper::client::connect::{Connected, Connection};
+use hyper::client::HttpConnector;
+use hyper::service::Service;
+use hyper::Uri;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+use super::HyperBackend;
+
+/// A connector that sends every request over a Unix domain socket.
+///
+/// The host of the request URI is ignored, so `http://localhost/containers/json` reaches
+/// whatever listens on the socket, e.g. the Docker daemon. [`UnixRouter`] only sends some hosts
+/// to sockets.
+#[derive(Debug, Clone)]
+pub struct UnixConnector {
+    path: Arc<Path>,
+}
+
+impl UnixConnector {
+    pub fn new(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().into(),
+        }
+    }
+}
+
+impl Service<Uri> for UnixConnector {
+    type Response = UnixStream;
+    type Error = io::Error;
+    type Future = Pin<Box<dyn Future<Output = io::Result<UnixStream>> + Send>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, _uri: Uri) -> Self::Future {
+        let path = self.path.clone();
+        Box::pin(async move {
+            tokio::net::UnixStream::connect(&*path)
+                .await
+                .map(UnixStream)
+        })
+    }
+}
+
+/// A connection opened by [`UnixConnector`].
+#[derive(Debug)]
+pub struct UnixStream(tokio::net::UnixStream);
+
+impl Connection for UnixStream {
+    fn connected(&self) -> Connected {
+        Connected::new()
+    }
+}
+
+impl AsyncRead for UnixStream {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_read(cx, buf)
+    }
+}
+
+impl AsyncWrite for UnixStream {
+    fn poll_write(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.0).poll_write(cx, buf)
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.0).poll_shutdown(cx)
+    }
+}
+
+impl HyperBackend<UnixConnector> {
+    /// Create a backend that sends every request over the Unix domain socket at `path`.
+    pub fn unix(path: impl AsRef<Path>) -> Self {
+        Self::with_connector(UnixConnector::new(path))
+    }
+}
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+/// A connector that sends requests for some hosts over Unix domain sockets and every other
+/// request through a fallback connector, plain TCP by default.
+///
+/// This lets one client talk to local daemons, e.g. `http://docker/containers/json`, and to
+/// remote services alike:
+///
+/// ```no_run
+/// # use zenwave::backend::{HyperBackend, UnixRouter};
+/// let connector = UnixRouter::new().resolve_to_uds("docker", "/var/run/docker.sock");
+/// let backend = HyperBackend::with_connector(connector);
+/// ```
+#[derive(Debug, Clone)]
+pub struct UnixRouter<C = HttpConnector> {
+    sockets: Arc<HashMap<String, Arc<Path>>>,
+    fallback: C,
+}
+
+impl UnixRouter {
+    pub fn new() -> Self {
+        Self::with_fallback(HttpConnector::new())
+    }
+}
+
+impl Default for UnixRouter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C> UnixRouter<C> {
+    /// Open connections to hosts without a socket with `fallback`.
+    pub fn with_fallback(fallback: C) -> Self {
+        Self {
+            sockets: Arc::default(),
+            fallback,
+        }
+    }
+
+    /// Connect to the socket at `path` for requests to `host`, whatever their port.
+    pub fn resolve_to_uds(mut self, host: impl Into<String>, path: impl AsRef<Path>) -> Self {
+        let host = host.into().to_ascii_lowercase();
+        Arc::make_mut(&mut self.sockets).insert(host, path.as_ref().into());
+        self
+    }
+
+    fn socket(&self, uri: &Uri) -> Option<Arc<Path>> {
+        let host = uri.host()?.to_ascii_lowercase();
+        self.sockets.get(&host).cloned()
+    }
+}
+
+impl<C> Service<Uri> for UnixRouter<C>
+where
+    C: Service<Uri>,
+    C::Response: Send + 'static,
+    C::Error: Into<BoxError>,
+    C::Future: Send + 'static,
+{
+    type Response = Routed<C::Response>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        self.fallback.poll_ready(cx).map_err(Into::into)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        match self.socket(&uri) {
+            Some(path) => Box::pin(async move {
+                let stream = tokio::net::UnixStream::connect(&*path).await?;
+                Ok::<_, BoxError>(Routed::Unix(UnixStream(stream)))
+            }),
+            None => {
+                let connecting = self.fallback.call(uri);
+                Box::pin(async move {
+                    let stream = connecting.await.map_err(Into::into)?;
+                    Ok::<_, BoxError>(Routed::Fallback(stream))
+                })
+            }
+        }
+    }
+}
+
+/// A connection opened by [`UnixRouter`], over a Unix domain socket or its fallback.
+#[derive(Debug)]
+pub enum Routed<T> {
+    Unix(UnixStream),
+    Fallback(T),
+}
+
+impl<T: Connection> Connection for Routed<T> {
+    fn connected(&self) -> Connected {
+        match self {
+            Routed::Unix(stream) => stream.connected(),
+            Routed::Fallback(stream) => stream.connected(),
+        }
+    }
+}
+
+impl<T: AsyncRead + Unpin> AsyncRead for Routed<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Routed::Unix(stream) => Pin::new(stream).poll_read(cx, buf),
+            Routed::Fallback(stream) => Pin::new(stream).poll_read(cx, buf),
+        }
+    }
+}
+
+impl<T: AsyncWrite + Unpin> AsyncWrite for Routed<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Routed::Unix(stream) => Pin::new(stream).poll_write(cx, buf),
+            Routed::Fallback(stream) => Pin::new(stream).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Routed::Unix(stream) => Pin::new(stream).poll_flush(cx),
+            Routed::Fallback(stream) => Pin::new(stream).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Routed::Unix(stream) => Pin::new(stream).poll_shutdown(cx),
+            Routed::Fallback(stream) => Pin::new(stream).poll_shutdown(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::UnixRouter;
+    use crate::backend::HyperBackend;
+    use crate::Client;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::os::unix::net::UnixListener;
+    use std::thread;
+
+    /// Answer one request on `stream` with `body`.
+    fn answer(mut stream: impl Read + Write, body: &str) {
+        let mut request = Vec::new();
+        let mut buffer = [0; 1024];
+        while !request.ends_with(b"\r\n\r\n") {
+            let read = stream.read(&mut buffer).unwrap();
+            assert!(read > 0, "the request was cut short");
+            request.extend_from_slice(&buffer[..read]);
+        }
+        let response = format!(
+            "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+            body.len()
+        );
+        stream.write_all(response.as_bytes()).unwrap();
+    }
+
+    #[tokio::test]
+    async fn hosts_map_to_sockets() {
+        let path = std::env::temp_dir().join(format!("zenwave-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let socket = UnixListener::bind(&path).unwrap();
+        thread::spawn(move || answer(socket.accept().unwrap().0, "unix"));
+        let tcp = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = tcp.local_addr().unwrap();
+        thread::spawn(move || answer(tcp.accept().unwrap().0, "tcp"));
+
+        let connector = UnixRouter::new().resolve_to_uds("Daemon", &path);
+        let client = Client::with_backend(HyperBackend::with_connector(connector));
+        let mut response = client.get("http://daemon:8080/info").await.unwrap();
+        assert_eq!(response.into_string().await.unwrap(), "unix");
+        let mut response = client.get(format!("http://{addr}/")).await.unwrap();
+        assert_eq!(response.into_string().await.unwrap(), "tcp");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,269 @@
+use crate::limit::{Acquired, RateLimit};
+use crate::{Client, ClientBackend, Result};
+use futures_util::future;
+use futures_util::stream::{self, Stream, StreamExt};
+use http_kit::{Request, Response};
+use std::collections::BTreeMap;
+use std::future::{Future, IntoFuture};
+use std::pin::Pin;
+use std::sync::Arc;
+
+const DEFAULT_CONCURRENCY: usize = 8;
+
+/// Many requests sent with bounded parallelism, created by [`Client::send_all`].
+///
+/// Await the batch to collect every result, or turn it into a [`Stream`] with
+/// [`Batch::stream`]; either way results come back in the order the requests were given, while
+/// up to the [concurrency](Batch::concurrency) limit of requests are in flight.
+/// [`Batch::stream_unordered`] yields them as they complete instead.
+pub struct Batch<'a, B> {
+    client: &'a Client<B>,
+    requests: Vec<Request>,
+    concurrency: usize,
+    fail_fast: bool,
+    limit: Option<Arc<RateLimit>>,
+}
+
+impl<'a, B: ClientBackend> Batch<'a, B> {
+    pub(crate) fn new(client: &'a Client<B>, requests: Vec<Request>) -> Self {
+        Self {
+            client,
+            requests,
+            concurrency: DEFAULT_CONCURRENCY,
+            fail_fast: false,
+            limit: None,
+        }
+    }
+
+    /// Keep at most `limit` requests in flight at once. Defaults to 8.
+    pub fn concurrency(mut self, limit: usize) -> Self {
+        self.concurrency = limit.max(1);
+        self
+    }
+
+    /// Stop at the first error: it's the last result yielded, and requests still in flight are
+    /// cancelled.
+    ///
+    /// The error is yielded as soon as it occurs, even by [`Batch::stream`] while earlier
+    /// requests are still in flight: those are cancelled too, and results that completed but
+    /// were waiting for them are dropped.
+    pub fn fail_fast(mut self) -> Self {
+        self.fail_fast = true;
+        self
+    }
+
+    /// Throttle the batch with `limit`, which may be shared with other batches and clients.
+    ///
+    /// If the client has the same limiter as middleware, requests of the batch only wait for
+    /// it once.
+    pub fn rate_limit(mut self, limit: Arc<RateLimit>) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn stream(self) -> impl Stream<Item = Result<Response>> + 'a {
+        let fail_fast = self.fail_fast;
+        let results: Pin<Box<dyn Stream<Item = _> + 'a>> = Box::pin(self.stream_unordered());
+        let state = (results, 0, BTreeMap::new(), false);
+        stream::unfold(
+            state,
+            move |(mut results, mut next, mut ready, done)| async move {
+                if done {
+                    return None;
+                }
+                loop {
+                    if let Some(result) = ready.remove(&next) {
+                        next += 1;
+                        return Some((result, (results, next, ready, false)));
+                    }
+                    let (index, result) = results.next().await?;
+                    if fail_fast && result.is_err() {
+                        return Some((result, (results, next, ready, true)));
+                    }
+                    ready.insert(index, result);
+                }
+            },
+        )
+    }
+
+    /// Yield results as they complete, each with the index of its request.
+    pub fn stream_unordered(self) -> impl Stream<Item = (usize, Result<Response>)> + 'a {
+        let Self {
+            client,
+            requests,
+            concurrency,
+            fail_fast,
+            limit,
+        } = self;
+        stream::iter(requests.into_iter().enumerate())
+            .map(move |(index, request)| {
+                let send = send(client, limit.clone(), request);
+                async move { (index, send.await) }
+            })
+            .buffer_unordered(concurrency)
+            .scan(false, move |failed, (index, result)| {
+                if *failed {
+                    return future::ready(None);
+                }
+                *failed = fail_fast && result.is_err();
+                future::ready(Some((index, result)))
+            })
+    }
+}
+
+async fn send<B: ClientBackend>(
+    client: &Client<B>,
+    limit: Option<Arc<RateLimit>>,
+    mut request: Request,
+) -> Result<Response> {
+    let _permit = match &limit {
+        Some(limit) => {
+            request.extensions_mut().insert(Acquired::by(limit));
+            limit
+                .acquire(request.uri().host().unwrap_or_default())
+                .await
+        }
+        None => None,
+    };
+    client.send(request).await
+}
+
+impl<'a, B: ClientBackend> IntoFuture for Batch<'a, B> {
+    type Output = Vec<Result<Response>>;
+
+    type IntoFuture = Pin<Box<dyn 'a + Future<Output = Self::Output>>>;
+
+    fn into_future(self) -> Self::IntoFuture {
+        Box::pin(self.stream().collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::limit::RateLimit;
+    use crate::{Client, ClientBackend};
+    use async_trait::async_trait;
+    use bytes::Bytes;
+    use futures_util::StreamExt;
+    use http_kit::{Body, Endpoint, Method, Request, Response};
+    use hyper::http;
+    use std::future::IntoFuture;
+    use std::io;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    /// Answers `/{ms}` after that many milliseconds with the path as body, and `/fail/{ms}` with
+    /// an error.
+    #[derive(Debug, Clone, Default)]
+    struct Delayed {
+        in_flight: Arc<AtomicUsize>,
+        peak: Arc<AtomicUsize>,
+        completed: Arc<AtomicUsize>,
+    }
+
+    #[async_trait]
+    impl Endpoint for Delayed {
+        async fn call_endpoint(&self, request: &mut Request) -> http_kit::Result<Response> {
+            let path = request.uri().path().to_owned();
+            let millis = path.rsplit('/').next().unwrap().parse().unwrap();
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.peak.fetch_max(in_flight, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(millis)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            self.completed.fetch_add(1, Ordering::SeqCst);
+            if path.starts_with("/fail/") {
+                return Err(io::Error::other("failed").into());
+            }
+            Ok(http::Response::new(Body::from(Bytes::from(path))).into())
+        }
+    }
+
+    impl ClientBackend for Delayed {}
+
+    fn requests(paths: &[&str]) -> Vec<Request> {
+        paths
+            .iter()
+            .map(|path| Request::new(Method::GET, format!("http://example.com{path}").as_str()))
+            .collect()
+    }
+
+    async fn bodies(results: Vec<crate::Result<Response>>) -> Vec<Bytes> {
+        let mut bodies = Vec::new();
+        for result in results {
+            bodies.push(result.unwrap().into_bytes().await.unwrap());
+        }
+        bodies
+    }
+
+    #[tokio::test]
+    async fn order() {
+        let client = Client::with_backend(Delayed::default());
+        let results = client.send_all(requests(&["/30", "/1", "/15"])).await;
+        assert_eq!(bodies(results).await, ["/30", "/1", "/15"]);
+
+        let batch = client.send_all(requests(&["/30", "/1", "/15"]));
+        let indices: Vec<usize> = batch
+            .stream_unordered()
+            .map(|(index, _)| index)
+            .collect()
+            .await;
+        assert_eq!(indices, [1, 2, 0]);
+    }
+
+    #[tokio::test]
+    async fn concurrency() {
+        let backend = Delayed::default();
+        let client = Client::with_backend(backend.clone());
+        let batch = client.send_all(requests(&["/5"; 6])).concurrency(2);
+        assert_eq!(batch.await.len(), 6);
+        assert_eq!(backend.peak.load(Ordering::SeqCst), 2);
+
+        let batch = client.send_all(requests(&["/5"; 3])).concurrency(0);
+        assert_eq!(
+            batch.await.len(),
+            3,
+            "a concurrency of 0 still makes progress"
+        );
+    }
+
+    #[tokio::test]
+    async fn fail_fast() {
+        let backend = Delayed::default();
+        let client = Client::with_backend(backend.clone());
+        let batch = client.send_all(requests(&["/500", "/fail/1", "/50"]));
+        let results = batch.fail_fast().await;
+        assert_eq!(results.len(), 1);
+        assert!(results[0].is_err());
+        // The requests around the failure were cancelled rather than waited for.
+        assert_eq!(backend.completed.load(Ordering::SeqCst), 1);
+
+        let results = client
+            .send_all(requests(&["/1", "/fail/5", "/500"]))
+            .fail_fast()
+            .stream_unordered()
+            .collect::<Vec<_>>()
+            .await;
+        let indices: Vec<usize> = results.iter().map(|(index, _)| *index).collect();
+        assert_eq!(indices, [0, 1]);
+
+        let results = client.send_all(requests(&["/fail/1", "/1"])).await;
+        assert_eq!(results.len(), 2, "failures don't stop a batch by default");
+    }
+
+    #[tokio::test]
+    async fn shared_rate_limit() {
+        let backend = Delayed::default();
+        let limit = Arc::new(RateLimit::new().per_host(1));
+        let client = Client::with_backend(backend.clone()).middleware(limit.clone());
+        let batch = client
+            .send_all(requests(&["/5", "/5", "/5"]))
+            .concurrency(3)
+            .rate_limit(limit);
+        let results = tokio::time::timeout(Duration::from_secs(5), batch.into_future())
+            .await
+            .expect("the batch and the client waited for each other");
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(backend.peak.load(Ordering::SeqCst), 1);
+    }
+}
@@ -0,0 +1,173 @@
+use crate::{Error, Result};
+use bytes::Bytes;
+use futures_util::stream;
+use http_kit::Body;
+use std::io;
+use tokio::sync::mpsc;
+
+/// Marks requests whose body is written by a [`BodySender`], in their extensions.
+///
+/// Such a body may only end after the response has arrived, so middleware must not wait for
+/// it to complete: buffering layers such as [`Compression`](crate::encoding::Compression) pass
+/// these requests through untouched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Streaming;
+
+/// How many chunks may wait to be written before [`BodySender::send`] waits.
+const BUFFER: usize = 16;
+
+/// The writing end of a request body that is produced while the request is in flight.
+///
+/// The response may arrive before the body is complete, so a body can stay open for as long
+/// as the exchange lasts. Dropping the sender ends the body; [`BodySender::abort`] cuts it
+/// short instead, so the server can tell it was truncated.
+#[derive(Debug)]
+pub struct BodySender {
+    sender: mpsc::Sender<io::Result<Bytes>>,
+}
+
+impl BodySender {
+    /// Create a sender and the body it writes to.
+    pub fn channel() -> (Self, Body) {
+        let (sender, receiver) = mpsc::channel(BUFFER);
+        let body = stream::unfold(receiver, |mut receiver| async move {
+            let chunk = receiver.recv().await?;
+            Some((chunk, receiver))
+        });
+        (Self { sender }, Body::from_stream(body))
+    }
+
+    /// Write a chunk, waiting while the connection is behind.
+    ///
+    /// Fails with [`Error::Io`] once the request has completed or failed and nobody reads the
+    /// body anymore.
+    pub async fn send(&self, chunk: impl Into<Bytes>) -> Result<()> {
+        self.sender
+            .send(Ok(chunk.into()))
+            .await
+            .map_err(|_| Error::Io(io::ErrorKind::BrokenPipe.into()))
+    }
+
+    /// Whether the body is no longer being read.
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+
+    /// End the body with an error, so it doesn't look complete to the server.
+    pub async fn abort(self) {
+        let error = io::Error::new(io::ErrorKind::Interrupted, "request body aborted");
+        let _ = self.sender.send(Err(error)).await;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::BodySender;
+    use crate::encoding::{Codec, Compression};
+    use crate::{Client, ClientBackend, Error};
+    use async_trait::async_trait;
+    use bytes::Bytes;
+    use futures_util::StreamExt;
+    use http_kit::header;
+    use http_kit::{Body, Endpoint, Request, Response};
+    use hyper::http;
+    use std::io;
+    use std::time::Duration;
+
+    /// Answers right away, without reading the request body.
+    struct Immediate;
+
+    #[async_trait]
+    impl Endpoint for Immediate {
+        async fn call_endpoint(&self, request: &mut Request) -> http_kit::Result<Response> {
+            let encoded = request.headers().contains_key(header::CONTENT_ENCODING);
+            let body = Body::from(Bytes::from(encoded.to_string()));
+            Ok(http::Response::new(body).into())
+        }
+    }
+
+    impl ClientBackend for Immediate {}
+
+    struct Identity;
+
+    impl Codec for Identity {
+        fn encode(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+            Ok(data.to_vec())
+        }
+
+        fn decode(&self, data: &[u8], _limit: usize) -> io::Result<Vec<u8>> {
+            Ok(data.to_vec())
+        }
+    }
+
+    #[tokio::test]
+    async fn buffering_middleware_passes_streams_through() {
+        let compression = Compression::new()
+            .codec("x-identity", Identity)
+            .encode_requests("x-identity");
+        let client = Client::with_backend(Immediate).middleware(compression);
+
+        let (sender, response) = client.post("http://example.com/").into_duplex();
+        let mut response = tokio::time::timeout(Duration::from_secs(5), response)
+            .await
+            .expect("the response shouldn't wait for the request body")
+            .unwrap();
+        assert_eq!(response.into_string().await.unwrap(), "false");
+        drop(sender);
+    }
+
+    #[tokio::test]
+    async fn chunks_arrive_in_order() {
+        let (sender, mut body) = BodySender::channel();
+        tokio::spawn(async move {
+            for chunk in ["a", "b", "c"] {
+                sender.send(chunk).await.unwrap();
+            }
+        });
+        let mut received = Vec::new();
+        while let Some(chunk) = body.next().await {
+            received.extend_from_slice(&chunk.unwrap());
+        }
+        assert_eq!(received, b"abc");
+    }
+
+    #[tokio::test]
+    async fn abort_fails_the_body() {
+        let (sender, mut body) = BodySender::channel();
+        sender.send("partial").await.unwrap();
+        sender.abort().await;
+        assert_eq!(&body.next().await.unwrap().unwrap()[..], b"partial");
+        assert!(body.next().await.unwrap().is_err());
+    }
+
+    #[tokio::test]
+    async fn send_fails_once_nobody_reads() {
+        let (sender, body) = BodySender::channel();
+        assert!(!sender.is_closed());
+        drop(body);
+        assert!(sender.is_closed());
+        assert!(matches!(sender.send("late").await, Err(Error::Io(_))));
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn stream_over_http() {
+        let server = crate::test_util::test_server()
+            .route(http::Method::POST, "/echo", |request| {
+                http::Response::new(request.into_body())
+            })
+            .start();
+
+        let client = Client::new();
+        let mut request = client.post(server.url("/echo").as_str());
+        let sender = request.body_sender();
+        let writer = tokio::spawn(async move {
+            for chunk in ["hello", ", ", "world"] {
+                sender.send(chunk).await.unwrap();
+            }
+        });
+        let mut response = request.await.unwrap();
+        writer.await.unwrap();
+        assert_eq!(response.into_string().await.unwrap(), "hello, world");
+    }
+}
@@ -0,0 +1,15 @@
+use http_kit::header::HeaderValue;
+use std::time::Duration;
+
+/// Settings of a [`Client`](crate::Client) that can change while it's in use.
+///
+/// Change them with [`Client::update`](crate::Client::update). Every request uses the settings
+/// current when it was sent, so an update never applies to half a request.
+#[derive(Debug, Clone, Default)]
+#[non_exhaustive]
+pub struct Config {
+    /// How long to wait for the response head, see [`Client::timeout`](crate::Client::timeout).
+    pub timeout: Option<Duration>,
+    /// The `User-Agent` of requests that don't set their own.
+    pub user_agent: Option<HeaderValue>,
+}
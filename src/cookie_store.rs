@@ -0,0 +1,507 @@
+use cookie::time::{Duration, OffsetDateTime};
+use cookie::Cookie;
+use http_kit::Uri;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+/// Cookies received from servers, stored and sent back following RFC 6265.
+///
+/// A cookie is identified by its name, domain and path, so a `Set-Cookie` for the same triple
+/// replaces the stored cookie while one with a different path is kept alongside it.
+///
+/// `Secure` cookies are only accepted from and sent to HTTPS origins unless `insecure` is
+/// passed, which is meant for testing against local plaintext servers.
+#[derive(Debug, Default)]
+pub(crate) struct CookieStore {
+    cookies: BTreeMap<Key, Entry>,
+    /// Creation order of the next cookie, which breaks ties when cookies are sent.
+    created: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+struct Key {
+    /// Empty for cookies added by hand without a domain, which are sent to every host.
+    domain: String,
+    path: String,
+    name: String,
+}
+
+#[derive(Debug)]
+struct Entry {
+    cookie: Cookie<'static>,
+    host_only: bool,
+    secure: bool,
+    expires: Option<OffsetDateTime>,
+    created: u64,
+    /// Whether the cookie was added by hand and is percent-encoded when sent. Cookies from
+    /// servers are sent back as they were received.
+    encode: bool,
+}
+
+/// A stored cookie in the stable form used by [`Archive`](crate::Archive).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(crate) struct ArchivedCookie {
+    name: String,
+    value: String,
+    domain: String,
+    path: String,
+    host_only: bool,
+    secure: bool,
+    http_only: bool,
+    /// Unix timestamp in seconds.
+    expires: Option<i64>,
+    #[serde(default)]
+    encode: bool,
+}
+
+impl CookieStore {
+    /// Add a cookie by hand. Unless it names a domain, it's sent to every host.
+    ///
+    /// Its name and value are percent-encoded when sent, so they can't smuggle in other cookies.
+    pub fn add(&mut self, cookie: Cookie<'static>) {
+        let domain = cookie.domain().map(normalize_domain).unwrap_or_default();
+        let path = match cookie.path() {
+            Some(path) if path.starts_with('/') => path.to_owned(),
+            _ => "/".to_owned(),
+        };
+        self.insert(domain, false, path, cookie, true);
+    }
+
+    /// Store the cookies of a `Set-Cookie` header received in response to a request to `uri`.
+    ///
+    /// Cookies that can't be parsed are ignored (RFC 6265, section 5.2) without affecting the
+    /// others of the header.
+    pub fn store(&mut self, uri: &Uri, header: &str, insecure: bool) {
+        let host = uri.host().unwrap_or_default().to_ascii_lowercase();
+        let secure = insecure || is_secure(uri);
+        for part in split_set_cookie(header) {
+            let cookie = match Cookie::parse(part.to_owned()) {
+                Ok(cookie) => cookie,
+                Err(_error) => {
+                    #[cfg(feature = "tracing")]
+                    tracing::debug!(error = %_error, "ignoring an invalid Set-Cookie");
+                    continue;
+                }
+            };
+            if cookie.secure() == Some(true) && !secure {
+                continue;
+            }
+            let (domain, host_only) = match cookie.domain() {
+                Some(domain) => {
+                    let domain = normalize_domain(domain);
+                    if is_public_suffix(&domain) {
+                        // A public suffix such as `com` is only accepted from that very host,
+                        // as a host-only cookie (RFC 6265, section 5.3).
+                        if domain != host {
+                            continue;
+                        }
+                        (host.clone(), true)
+                    } else if domain_matches(&host, &domain) {
+                        (domain, false)
+                    } else {
+                        // A server may only set cookies for its own domain or a parent of it.
+                        continue;
+                    }
+                }
+                None => (host.clone(), true),
+            };
+            let path = match cookie.path() {
+                Some(path) if path.starts_with('/') => path.to_owned(),
+                _ => default_path(uri.path()).to_owned(),
+            };
+            self.insert(domain, host_only, path, cookie, false);
+        }
+    }
+
+    fn insert(
+        &mut self,
+        domain: String,
+        host_only: bool,
+        path: String,
+        cookie: Cookie<'static>,
+        encode: bool,
+    ) {
+        let key = Key {
+            domain,
+            path,
+            name: cookie.name().to_owned(),
+        };
+        let now = OffsetDateTime::now_utc();
+        // `Max-Age` takes precedence over `Expires`.
+        let expires = match cookie.max_age() {
+            Some(max_age) if max_age <= Duration::ZERO => Some(OffsetDateTime::UNIX_EPOCH),
+            Some(max_age) => now.checked_add(max_age),
+            None => cookie.expires_datetime(),
+        };
+
+        // An already expired cookie is how servers delete one.
+        if expires.is_some_and(|expires| expires <= now) {
+            self.cookies.remove(&key);
+            return;
+        }
+
+        let secure = cookie.secure().unwrap_or(false);
+        // A replaced cookie keeps its creation time (RFC 6265, section 5.3).
+        let created = match self.cookies.get(&key) {
+            Some(entry) => entry.created,
+            None => self.next_created(),
+        };
+        self.cookies.insert(
+            key,
+            Entry {
+                cookie,
+                host_only,
+                secure,
+                expires,
+                created,
+                encode,
+            },
+        );
+    }
+
+    fn next_created(&mut self) -> u64 {
+        self.created += 1;
+        self.created
+    }
+
+    /// The `Cookie` header to send with a request to `uri`, if any stored cookie applies.
+    pub fn header(&self, uri: &Uri, insecure: bool) -> Option<String> {
+        let host = uri.host().unwrap_or_default().to_ascii_lowercase();
+        let secure = insecure || is_secure(uri);
+        let now = OffsetDateTime::now_utc();
+
+        let mut matched: Vec<(&Key, &Entry)> = self
+            .cookies
+            .iter()
+            .filter(|(key, entry)| {
+                let domain = if key.domain.is_empty() {
+                    true
+                } else if entry.host_only {
+                    host == key.domain
+                } else {
+                    domain_matches(&host, &key.domain)
+                };
+                domain
+                    && path_matches(uri.path(), &key.path)
+                    && (secure || !entry.secure)
+                    && entry.expires.is_none_or(|expires| expires > now)
+            })
+            .collect();
+        if matched.is_empty() {
+            return None;
+        }
+
+        // Cookies with longer paths are listed first, then older ones (RFC 6265, section 5.4).
+        matched.sort_by(|(a, a_entry), (b, b_entry)| {
+            b.path
+                .len()
+                .cmp(&a.path.len())
+                .then(a_entry.created.cmp(&b_entry.created))
+        });
+        let pairs: Vec<String> = matched
+            .iter()
+            .map(|(_, entry)| match entry.encode {
+                true => entry.cookie.encoded().stripped().to_string(),
+                false => entry.cookie.stripped().to_string(),
+            })
+            .collect();
+        Some(pairs.join("; "))
+    }
+
+    /// The cookies that haven't expired, oldest first so that importing them keeps the order
+    /// they're sent in.
+    pub fn export(&self) -> Vec<ArchivedCookie> {
+        let now = OffsetDateTime::now_utc();
+        let mut cookies: Vec<(&Key, &Entry)> = self
+            .cookies
+            .iter()
+            .filter(|(_, entry)| entry.expires.is_none_or(|expires| expires > now))
+            .collect();
+        cookies.sort_by_key(|(_, entry)| entry.created);
+        cookies
+            .into_iter()
+            .map(|(key, entry)| ArchivedCookie {
+                name: key.name.clone(),
+                value: entry.cookie.value().to_owned(),
+                domain: key.domain.clone(),
+                path: key.path.clone(),
+                host_only: entry.host_only,
+                secure: entry.secure,
+                http_only: entry.cookie.http_only().unwrap_or(false),
+                expires: entry.expires.map(OffsetDateTime::unix_timestamp),
+                encode: entry.encode,
+            })
+            .collect()
+    }
+
+    /// Add exported cookies, replacing stored cookies with the same name, domain and path.
+    pub fn import(&mut self, cookies: Vec<ArchivedCookie>) {
+        let now = OffsetDateTime::now_utc();
+        for archived in cookies {
+            let expires = match archived.expires.map(OffsetDateTime::from_unix_timestamp) {
+                Some(Ok(expires)) if expires > now => Some(expires),
+                Some(_) => continue,
+                None => None,
+            };
+            let cookie = Cookie::build((archived.name.clone(), archived.value))
+                .secure(archived.secure)
+                .http_only(archived.http_only)
+                .build();
+            let created = self.next_created();
+            self.cookies.insert(
+                Key {
+                    domain: archived.domain,
+                    path: archived.path,
+                    name: archived.name,
+                },
+                Entry {
+                    cookie,
+                    host_only: archived.host_only,
+                    secure: archived.secure,
+                    expires,
+                    created,
+                    encode: archived.encode,
+                },
+            );
+        }
+    }
+}
+
+/// Split a `Set-Cookie` header that several cookies have been folded into.
+///
+/// A comma only starts a new cookie when it's followed by a `name=` pair, so the comma of an
+/// `Expires` date such as `Wed, 21 Oct 2015 07:28:00 GMT` is left alone.
+fn split_set_cookie(header: &str) -> impl Iterator<Item = &str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    for (index, _) in header.match_indices(',') {
+        if starts_cookie(&header[index + 1..]) {
+            parts.push(&header[start..index]);
+            start = index + 1;
+        }
+    }
+    parts.push(&header[start..]);
+    parts
+        .into_iter()
+        .map(str::trim)
+        .filter(|part| !part.is_empty())
+}
+
+fn starts_cookie(rest: &str) -> bool {
+    let rest = rest.trim_start();
+    match rest.find('=') {
+        Some(end) => {
+            end > 0 && !rest[..end].contains(|c: char| c == ';' || c == ',' || c.is_whitespace())
+        }
+        None => false,
+    }
+}
+
+fn is_secure(uri: &Uri) -> bool {
+    uri.scheme_str() == Some("https")
+}
+
+fn normalize_domain(domain: &str) -> String {
+    domain.trim_start_matches('.').to_ascii_lowercase()
+}
+
+fn is_public_suffix(domain: &str) -> bool {
+    psl::suffix_str(domain) == Some(domain)
+}
+
+fn domain_matches(host: &str, domain: &str) -> bool {
+    host == domain
+        || (host.len() > domain.len()
+            && host.ends_with(domain)
+            && host[..host.len() - domain.len()].ends_with('.')
+            && host.parse::<IpAddr>().is_err())
+}
+
+fn path_matches(path: &str, cookie_path: &str) -> bool {
+    path == cookie_path
+        || (path.starts_with(cookie_path)
+            && (cookie_path.ends_with('/') || path[cookie_path.len()..].starts_with('/')))
+}
+
+fn default_path(path: &str) -> &str {
+    match path.rfind('/') {
+        Some(0) | None => "/",
+        Some(index) => &path[..index],
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::CookieStore;
+    use cookie::Cookie;
+    use http_kit::Uri;
+    use std::fs;
+    use std::path::Path;
+
+    fn uri(uri: &'static str) -> Uri {
+        Uri::from_static(uri)
+    }
+
+    #[test]
+    fn folded_header_with_expires() {
+        let mut store = CookieStore::default();
+        let origin = uri("http://example.com/");
+        store
+            .store(
+                &origin,
+                "a=1; Expires=Wed, 21 Oct 2099 07:28:00 GMT; Path=/, b=2, c=3; Expires=Thu, 01 Jan 1970 00:00:00 GMT",
+                false,
+            );
+
+        assert_eq!(store.header(&origin, false).as_deref(), Some("a=1; b=2"));
+    }
+
+    #[test]
+    fn cookies_added_by_hand_are_encoded() {
+        let mut store = CookieStore::default();
+        let origin = uri("http://example.com/");
+        store.add(Cookie::new("a", "1; admin=true"));
+        store.store(&origin, "b=%20", false);
+        assert_eq!(
+            store.header(&origin, false).as_deref(),
+            Some("a=1%3B%20admin%3Dtrue; b=%20")
+        );
+
+        let mut imported = CookieStore::default();
+        imported.import(store.export());
+        assert_eq!(
+            imported.header(&origin, false),
+            store.header(&origin, false)
+        );
+    }
+
+    #[test]
+    fn invalid_cookies_are_ignored() {
+        let mut store = CookieStore::default();
+        let origin = uri("http://example.com/");
+        store.store(&origin, "=1, b=2", false);
+        store.store(&origin, "c", false);
+        assert_eq!(store.header(&origin, false).as_deref(), Some("b=2"));
+    }
+
+    #[test]
+    fn public_suffix_domain() {
+        let mut store = CookieStore::default();
+        store.store(&uri("http://example.com/"), "a=1; Domain=com", false);
+        store.store(&uri("http://example.co.uk/"), "b=1; Domain=co.uk", false);
+        assert_eq!(store.header(&uri("http://other.com/"), false), None);
+        assert_eq!(store.header(&uri("http://example.com/"), false), None);
+        assert_eq!(store.header(&uri("http://other.co.uk/"), false), None);
+
+        // A host that is itself a public suffix may set a host-only cookie for itself.
+        store.store(&uri("http://com/"), "c=1; Domain=com", false);
+        assert_eq!(
+            store.header(&uri("http://com/"), false).as_deref(),
+            Some("c=1")
+        );
+        assert_eq!(store.header(&uri("http://example.com/"), false), None);
+    }
+
+    /// Runs the cases in `testdata/http-state`. They are written for this crate and aren't the
+    /// http-state suite (https://github.com/abarth/http-state) itself, only follow its format:
+    /// the `Set-Cookie` headers of `NAME-test` are
+    /// received from `/cookie-parser`, and `NAME-expected` holds the `Cookie` header then sent
+    /// to `/cookie-parser-result`, if any. A `Location` header changes where that goes.
+    #[test]
+    fn http_state() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("testdata/http-state");
+        let mut cases = 0;
+        for entry in fs::read_dir(&dir).unwrap() {
+            let path = entry.unwrap().path();
+            let name = path.file_name().and_then(|name| name.to_str());
+            let Some(name) = name.and_then(|name| name.strip_suffix("-test")) else {
+                continue;
+            };
+            let test = fs::read_to_string(&path).unwrap();
+            let expected = fs::read_to_string(dir.join(format!("{name}-expected"))).unwrap();
+
+            let origin = "http://home.example.org:8888";
+            let mut store = CookieStore::default();
+            let parser: Uri = format!("{origin}/cookie-parser?{name}").parse().unwrap();
+            let mut result = format!("{origin}/cookie-parser-result?{name}");
+            for line in test.lines() {
+                match line.split_once(':') {
+                    Some((field, value)) if field.eq_ignore_ascii_case("set-cookie") => {
+                        store.store(&parser, value.trim(), false);
+                    }
+                    Some((field, value)) if field.eq_ignore_ascii_case("location") => {
+                        result = format!("{origin}{}", value.trim());
+                    }
+                    _ => {}
+                }
+            }
+
+            let cookie = store.header(&result.parse().unwrap(), false);
+            let expected = expected.trim().strip_prefix("Cookie:").map(str::trim);
+            assert_eq!(cookie.as_deref(), expected, "{name}");
+            cases += 1;
+        }
+        assert!(cases > 0);
+    }
+
+    #[test]
+    fn overwrite_and_delete() {
+        let mut store = CookieStore::default();
+        let origin = uri("http://example.com/docs/page");
+        store.store(&origin, "a=1; Path=/", false);
+        store.store(&origin, "a=2; Path=/", false);
+        store.store(&origin, "a=3", false);
+        assert_eq!(
+            store.header(&origin, false).as_deref(),
+            Some("a=3; a=2"),
+            "a cookie with another path is kept alongside"
+        );
+
+        store.store(&origin, "a=; Path=/; Max-Age=0", false);
+        assert_eq!(store.header(&origin, false).as_deref(), Some("a=3"));
+        assert_eq!(store.header(&uri("http://example.com/"), false), None);
+    }
+
+    #[test]
+    fn domain_path_and_secure() {
+        let mut store = CookieStore::default();
+        let origin = uri("https://www.example.com/");
+        store.store(&origin, "shared=1; Domain=.example.com", false);
+        store.store(&origin, "host=1", false);
+        store.store(&origin, "secret=1; Secure", false);
+        store.store(&origin, "other=1; Domain=example.org", false);
+        store.store(&origin, "api=1; Path=/api", false);
+
+        assert_eq!(
+            store
+                .header(&uri("http://api.example.com/api"), false)
+                .as_deref(),
+            Some("shared=1")
+        );
+        assert_eq!(
+            store
+                .header(&uri("https://www.example.com/apix"), false)
+                .as_deref(),
+            Some("shared=1; host=1; secret=1")
+        );
+        assert_eq!(
+            store
+                .header(&uri("https://www.example.com/api/v1"), false)
+                .as_deref(),
+            Some("api=1; shared=1; host=1; secret=1")
+        );
+    }
+
+    #[test]
+    fn insecure_origin() {
+        let mut store = CookieStore::default();
+        let origin = uri("http://localhost/");
+        store.store(&origin, "session=1; Secure", false);
+        assert_eq!(store.header(&origin, true), None);
+
+        store.store(&origin, "session=1; Secure", true);
+        assert_eq!(store.header(&origin, false), None);
+        assert_eq!(store.header(&origin, true).as_deref(), Some("session=1"));
+    }
+}
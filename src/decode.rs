@@ -0,0 +1,203 @@
+//! Decoding response bodies according to their `Content-Type`.
+
+use crate::{Error, Result};
+use http_kit::{header, Response};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Turns a response body of some media type into a structured value.
+///
+/// A decoder doesn't know the type [`ResponseExt::decode`](crate::ResponseExt::decode) was
+/// asked for: it creates a `serde` deserializer for the body and hands it to the [`Sink`],
+/// which deserializes the requested type straight from it. A decoder for CBOR, MessagePack or
+/// XML is therefore only written once for every target type, without going through an
+/// intermediate value. Closures taking the body bytes and the sink are decoders too.
+pub trait Decoder: Send + Sync {
+    fn decode(&self, body: &[u8], sink: Sink<'_>) -> std::result::Result<(), BoxError>;
+}
+
+impl<F> Decoder for F
+where
+    F: Fn(&[u8], Sink<'_>) -> std::result::Result<(), BoxError> + Send + Sync,
+{
+    fn decode(&self, body: &[u8], sink: Sink<'_>) -> std::result::Result<(), BoxError> {
+        self(body, sink)
+    }
+}
+
+/// Deserializes the type the caller asked for.
+type DeserializeFn<'a> =
+    dyn FnMut(&mut dyn erased_serde::Deserializer<'_>) -> erased_serde::Result<()> + 'a;
+
+/// Where a [`Decoder`] sends the deserializer for a body, see [`Sink::deserialize`].
+pub struct Sink<'a>(&'a mut DeserializeFn<'a>);
+
+impl Sink<'_> {
+    /// Deserialize the type the caller asked for from `deserializer`.
+    pub fn deserialize<'de, D: serde::Deserializer<'de>>(
+        self,
+        deserializer: D,
+    ) -> std::result::Result<(), BoxError> {
+        let mut deserializer = <dyn erased_serde::Deserializer>::erase(deserializer);
+        (self.0)(&mut deserializer)?;
+        Ok(())
+    }
+}
+
+impl Debug for Sink<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Sink").finish_non_exhaustive()
+    }
+}
+
+struct Json;
+
+impl Decoder for Json {
+    fn decode(&self, body: &[u8], sink: Sink<'_>) -> std::result::Result<(), BoxError> {
+        let mut deserializer = serde_json::Deserializer::from_slice(body);
+        sink.deserialize(&mut deserializer)?;
+        // Reject trailing data, like `serde_json::from_slice`.
+        deserializer.end()?;
+        Ok(())
+    }
+}
+
+/// The decoders of a client by media type, attached to the extensions of every response.
+#[derive(Clone)]
+pub(crate) struct Decoders(Arc<HashMap<String, Arc<dyn Decoder>>>);
+
+impl Decoders {
+    pub fn insert(&mut self, media_type: &str, decoder: Arc<dyn Decoder>) {
+        Arc::make_mut(&mut self.0).insert(media_type.to_ascii_lowercase(), decoder);
+    }
+
+    /// The decoder for a `Content-Type`, ignoring its parameters.
+    ///
+    /// A type with a structured syntax suffix such as `application/problem+json` falls back to
+    /// the decoder of `application/json`.
+    fn get(&self, content_type: &str) -> Option<&dyn Decoder> {
+        let essence = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        let decoder = self.0.get(&essence).or_else(|| {
+            let (_, suffix) = essence.rsplit_once('+')?;
+            self.0.get(&format!("application/{suffix}"))
+        });
+        decoder.map(AsRef::as_ref)
+    }
+}
+
+impl Default for Decoders {
+    fn default() -> Self {
+        let mut decoders = Self(Arc::default());
+        decoders.insert("application/json", Arc::new(Json));
+        decoders
+    }
+}
+
+impl Debug for Decoders {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.0.keys()).finish()
+    }
+}
+
+/// Decode the body of `response` with the decoder registered for its `Content-Type`.
+pub(crate) async fn decode<T: DeserializeOwned>(response: &mut Response) -> Result<T> {
+    let decoders = response
+        .extensions()
+        .get::<Decoders>()
+        .cloned()
+        .unwrap_or_default();
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default()
+        .to_owned();
+    let decoder = decoders
+        .get(&content_type)
+        .ok_or(Error::UnsupportedMediaType(content_type))?;
+
+    let body = response.into_bytes().await?;
+    let mut value = None;
+    let mut deserialize = |deserializer: &mut dyn erased_serde::Deserializer<'_>| {
+        value = Some(erased_serde::deserialize(deserializer)?);
+        Ok::<_, erased_serde::Error>(())
+    };
+    decoder
+        .decode(&body, Sink(&mut deserialize))
+        .map_err(Error::Decode)?;
+    value.ok_or_else(|| Error::Decode("the decoder didn't deserialize the body".into()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{decode, BoxError, Decoders, Sink};
+    use http_kit::{header, Body, Response};
+    use hyper::http;
+    use serde::Deserialize;
+    use std::sync::Arc;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    fn response(content_type: &str, body: &'static str) -> Response {
+        let mut response: Response = http::Response::builder()
+            .header(header::CONTENT_TYPE, content_type)
+            .body(Body::from(bytes::Bytes::from(body)))
+            .unwrap()
+            .into();
+        let mut decoders = Decoders::default();
+        // Plain `x,y` pairs, deserialized through a sequence of the two numbers.
+        let csv = |body: &[u8], sink: Sink<'_>| -> Result<(), BoxError> {
+            let text = std::str::from_utf8(body)?;
+            let numbers = text
+                .trim()
+                .split(',')
+                .map(str::parse)
+                .collect::<Result<Vec<i32>, _>>()?;
+            let deserializer = serde::de::value::SeqDeserializer::<_, serde::de::value::Error>::new(
+                numbers.into_iter(),
+            );
+            sink.deserialize(deserializer)
+        };
+        decoders.insert("text/csv", Arc::new(csv));
+        response.extensions_mut().insert(decoders);
+        response
+    }
+
+    #[tokio::test]
+    async fn decoders() {
+        let mut json = response("application/json", r#"{"x":1,"y":2}"#);
+        assert_eq!(
+            decode::<Point>(&mut json).await.unwrap(),
+            Point { x: 1, y: 2 }
+        );
+
+        let mut csv = response("text/csv", "3,4");
+        let point: (i32, i32) = decode(&mut csv).await.unwrap();
+        assert_eq!(point, (3, 4));
+
+        let mut trailing = response("application/json", r#"{"x":1,"y":2} 3"#);
+        assert!(decode::<Point>(&mut trailing).await.is_err());
+    }
+
+    #[test]
+    fn media_type_lookup() {
+        let decoders = Decoders::default();
+        assert!(decoders.get("application/json; charset=utf-8").is_some());
+        assert!(decoders.get("Application/Problem+JSON").is_some());
+        assert!(decoders.get("application/cbor").is_none());
+        assert!(decoders.get("").is_none());
+    }
+}
@@ -0,0 +1,293 @@
+use crate::{Client, ClientBackend, IntoUri};
+use http_kit::Method;
+use std::fmt::{self, Display};
+use std::net::SocketAddr;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A step of the connectivity check run by [`Client::diagnose`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Stage {
+    Uri,
+    Dns,
+    Tcp,
+    Tls,
+    Proxy,
+    Http,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Outcome {
+    Passed(String),
+    Failed(String),
+    Skipped(String),
+}
+
+#[derive(Debug, Clone)]
+pub struct StageReport {
+    pub stage: Stage,
+    pub outcome: Outcome,
+    pub elapsed: Duration,
+}
+
+/// The result of [`Client::diagnose`], one entry per stage that was attempted.
+///
+/// Stages run in order and the check stops at the first failure, so the last entry of a failed
+/// report explains why the endpoint can't be reached.
+#[derive(Debug, Clone, Default)]
+pub struct Report {
+    pub stages: Vec<StageReport>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.failure().is_none()
+    }
+
+    /// The stage that failed, if any.
+    pub fn failure(&self) -> Option<&StageReport> {
+        self.stages
+            .iter()
+            .find(|stage| matches!(stage.outcome, Outcome::Failed(_)))
+    }
+
+    fn push(&mut self, stage: Stage, start: Instant, outcome: Outcome) -> bool {
+        let passed = !matches!(outcome, Outcome::Failed(_));
+        self.stages.push(StageReport {
+            stage,
+            outcome,
+            elapsed: start.elapsed(),
+        });
+        passed
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for report in &self.stages {
+            let (status, detail) = match &report.outcome {
+                Outcome::Passed(detail) => ("ok", detail),
+                Outcome::Failed(detail) => ("failed", detail),
+                Outcome::Skipped(detail) => ("skipped", detail),
+            };
+            writeln!(
+                f,
+                "{:?}: {status} after {:?}: {detail}",
+                report.stage, report.elapsed
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl<B: ClientBackend> Client<B> {
+    /// Check step by step whether `uri` can be reached and report which stage fails and why.
+    ///
+    /// The DNS and TCP stages use the system resolver and a direct TCP connection, while the
+    /// HTTP stage sends a `HEAD` request through this client, including its middleware. Those
+    /// stages are only checked if the backend connects the same way, see
+    /// [`ClientBackend::system_connector`]; otherwise they're skipped, since a custom resolver
+    /// or transport could reach hosts the system can't. TLS and proxies depend on the backend
+    /// too, so their stages are always skipped and only covered by the HTTP stage.
+    pub async fn diagnose(&self, uri: impl IntoUri) -> Report {
+        let mut report = Report::default();
+
+        let start = Instant::now();
+        let uri = match uri.into_uri(self.urls.as_ref()) {
+            Ok(uri) => uri,
+            Err(error) => {
+                report.push(Stage::Uri, start, Outcome::Failed(error.to_string()));
+                return report;
+            }
+        };
+        let https = uri.scheme_str() == Some("https");
+        let port = uri.port_u16().unwrap_or(if https { 443 } else { 80 });
+        let Some(host) = uri.host() else {
+            report.push(
+                Stage::Uri,
+                start,
+                Outcome::Failed("the uri has no host".into()),
+            );
+            return report;
+        };
+        report.push(Stage::Uri, start, Outcome::Passed(uri.to_string()));
+
+        if self.backend.system_connector() {
+            if !check_connection(&mut report, host, port).await {
+                return report;
+            }
+        } else {
+            for stage in [Stage::Dns, Stage::Tcp] {
+                let outcome = Outcome::Skipped("the backend has its own connector".into());
+                report.push(stage, Instant::now(), outcome);
+            }
+        }
+
+        // TLS and proxies are up to the backend, which may be any connector, so they're only
+        // exercised by the HTTP stage.
+        let outcome = if https {
+            Outcome::Skipped("not checked".into())
+        } else {
+            Outcome::Skipped("plain HTTP".into())
+        };
+        report.push(Stage::Tls, Instant::now(), outcome);
+
+        let outcome = match proxy_variable(https) {
+            Some(name) => Outcome::Skipped(format!("{name} is set, but not checked")),
+            None => Outcome::Skipped("not checked".into()),
+        };
+        report.push(Stage::Proxy, Instant::now(), outcome);
+
+        let start = Instant::now();
+        let outcome = match self.method(Method::HEAD, uri).await {
+            Ok(response) => Outcome::Passed(format!("status {}", response.status())),
+            Err(error) => Outcome::Failed(error.to_string()),
+        };
+        report.push(Stage::Http, start, outcome);
+        report
+    }
+}
+
+/// Resolve `host` with the system resolver and connect to it, pushing the DNS and TCP stages.
+/// Returns whether both passed.
+async fn check_connection(report: &mut Report, host: &str, port: u16) -> bool {
+    let start = Instant::now();
+    let addrs: Vec<SocketAddr> = match tokio::net::lookup_host((host, port)).await {
+        Ok(addrs) => addrs.collect(),
+        Err(error) => {
+            report.push(Stage::Dns, start, Outcome::Failed(error.to_string()));
+            return false;
+        }
+    };
+    let resolved = addrs
+        .iter()
+        .map(|addr| addr.ip().to_string())
+        .collect::<Vec<_>>()
+        .join(", ");
+    report.push(Stage::Dns, start, Outcome::Passed(resolved));
+
+    let start = Instant::now();
+    let mut errors = Vec::new();
+    let mut connected = None;
+    for addr in &addrs {
+        match tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(addr)).await {
+            Ok(Ok(_)) => {
+                connected = Some(addr);
+                break;
+            }
+            Ok(Err(error)) => errors.push(format!("{addr}: {error}")),
+            Err(_) => errors.push(format!("{addr}: timed out")),
+        }
+    }
+    let outcome = match connected {
+        Some(addr) => Outcome::Passed(format!("connected to {addr}")),
+        None if errors.is_empty() => Outcome::Failed("no addresses to connect to".into()),
+        None => Outcome::Failed(errors.join("; ")),
+    };
+    report.push(Stage::Tcp, start, outcome)
+}
+
+/// The proxy variable of the environment that applies to the scheme, if any is set.
+fn proxy_variable(https: bool) -> Option<&'static str> {
+    let names: &[&'static str] = if https {
+        &["HTTPS_PROXY", "https_proxy", "ALL_PROXY", "all_proxy"]
+    } else {
+        &["HTTP_PROXY", "http_proxy", "ALL_PROXY", "all_proxy"]
+    };
+    names
+        .iter()
+        .copied()
+        .find(|name| std::env::var_os(name).is_some_and(|value| !value.is_empty()))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Outcome, Stage};
+    use crate::backend::{HyperBackend, Resolver};
+    use crate::{Client, ClientBackend};
+    use async_trait::async_trait;
+    use http_kit::{Body, Endpoint, Request, Response};
+    use hyper::http;
+
+    /// Answers every request with an empty `200`.
+    struct Answer;
+
+    #[async_trait]
+    impl Endpoint for Answer {
+        async fn call_endpoint(&self, _request: &mut Request) -> http_kit::Result<Response> {
+            Ok(http::Response::new(Body::empty()).into())
+        }
+    }
+
+    impl ClientBackend for Answer {}
+
+    fn stages(report: &super::Report) -> Vec<(Stage, &Outcome)> {
+        report
+            .stages
+            .iter()
+            .map(|stage| (stage.stage, &stage.outcome))
+            .collect()
+    }
+
+    #[test]
+    fn system_connector() {
+        assert!(HyperBackend::new().system_connector());
+        assert!(!HyperBackend::with_resolver(Resolver::new()).system_connector());
+        assert!(!Answer.system_connector());
+    }
+
+    #[tokio::test]
+    async fn custom_backends_skip_dns_and_tcp() {
+        // The host doesn't resolve, but the backend doesn't need it to.
+        let report = Client::with_backend(Answer)
+            .diagnose("http://unresolvable.invalid/")
+            .await;
+        assert!(report.is_ok(), "{report}");
+        let skipped = Outcome::Skipped("the backend has its own connector".into());
+        let stages = stages(&report);
+        assert_eq!(stages[1], (Stage::Dns, &skipped));
+        assert_eq!(stages[2], (Stage::Tcp, &skipped));
+        assert_eq!(
+            stages.last(),
+            Some(&(Stage::Http, &Outcome::Passed("status 200 OK".into())))
+        );
+    }
+
+    #[tokio::test]
+    async fn uri_without_host() {
+        let report = Client::with_backend(Answer).diagnose("/path").await;
+        let failure = report.failure().unwrap();
+        assert_eq!(failure.stage, Stage::Uri);
+        assert_eq!(report.stages.len(), 1);
+    }
+
+    #[cfg(feature = "test-util")]
+    #[tokio::test]
+    async fn system_connector_checks_every_stage() {
+        let server = crate::test_util::test_server()
+            .route(http::Method::HEAD, "/", |_| {
+                http::Response::new(Default::default())
+            })
+            .start();
+        let report = Client::new().diagnose(server.url("/")).await;
+        assert!(report.is_ok(), "{report}");
+        let stages: Vec<Stage> = report.stages.iter().map(|stage| stage.stage).collect();
+        assert_eq!(
+            stages,
+            [
+                Stage::Uri,
+                Stage::Dns,
+                Stage::Tcp,
+                Stage::Tls,
+                Stage::Proxy,
+                Stage::Http
+            ]
+        );
+        assert!(matches!(report.stages[1].outcome, Outcome::Passed(_)));
+        assert!(matches!(report.stages[2].outcome, Outcome::Passed(_)));
+    }
+}
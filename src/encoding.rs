@@ -0,0 +1,299 @@
+//! Custom `Content-Encoding` codecs.
+
+use crate::{Error, Middleware, Next, Result, Streaming};
+use async_trait::async_trait;
+use http_kit::header::{self, HeaderValue};
+use http_kit::{Request, Response};
+use std::fmt::{self, Debug};
+use std::io;
+use std::sync::Arc;
+
+/// How large a decoded response body may get by default, see [`Compression::max_decoded_size`].
+const MAX_DECODED_SIZE: usize = 64 * 1024 * 1024;
+
+/// A content coding such as lz4 or snappy, applied to whole bodies.
+pub trait Codec: Send + Sync {
+    fn encode(&self, data: &[u8]) -> io::Result<Vec<u8>>;
+
+    /// Decode `data`, failing as soon as the output would grow past `limit` bytes.
+    ///
+    /// Never called with empty data. The output is checked against `limit` afterwards as well,
+    /// but only a codec can stop a small body from expanding into a huge allocation.
+    fn decode(&self, data: &[u8], limit: usize) -> io::Result<Vec<u8>>;
+}
+
+/// Middleware negotiating and applying the content codings registered with it.
+///
+/// Every request advertises the registered codings in `Accept-Encoding`, preferring those
+/// registered first, and responses in any of them are decoded transparently. Request bodies
+/// can be encoded as well with [`Compression::encode_requests`]. Bodies are buffered to be
+/// encoded or decoded; [streaming](crate::Streaming) request bodies, empty bodies such as those
+/// of `HEAD` requests and `304` responses, and responses in codings nobody registered are
+/// passed through untouched.
+#[derive(Clone)]
+pub struct Compression {
+    codecs: Vec<(String, Arc<dyn Codec>)>,
+    encode_requests: Option<String>,
+    max_decoded_size: usize,
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self {
+            codecs: Vec::new(),
+            encode_requests: None,
+            max_decoded_size: MAX_DECODED_SIZE,
+        }
+    }
+}
+
+impl Compression {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register `codec` under the content coding `name`, e.g. `lz4`.
+    pub fn codec(mut self, name: &str, codec: impl Codec + 'static) -> Self {
+        let name = name.to_ascii_lowercase();
+        self.codecs.retain(|(registered, _)| *registered != name);
+        self.codecs.push((name, Arc::new(codec)));
+        self
+    }
+
+    /// Encode request bodies with the codec registered as `name`.
+    ///
+    /// Requests fail with [`Error::UnsupportedEncoding`] if no codec is registered as `name`.
+    pub fn encode_requests(mut self, name: &str) -> Self {
+        self.encode_requests = Some(name.to_ascii_lowercase());
+        self
+    }
+
+    /// Fail responses whose body decodes to more than `bytes` with [`Error::Io`]. Defaults to
+    /// 64 MiB.
+    pub fn max_decoded_size(mut self, bytes: usize) -> Self {
+        self.max_decoded_size = bytes;
+        self
+    }
+
+    fn get(&self, name: &str) -> Option<&dyn Codec> {
+        self.codecs
+            .iter()
+            .find(|(registered, _)| registered.eq_ignore_ascii_case(name))
+            .map(|(_, codec)| codec.as_ref())
+    }
+
+    fn accept_encoding(&self) -> Option<HeaderValue> {
+        let names: Vec<&str> = self.codecs.iter().map(|(name, _)| name.as_str()).collect();
+        HeaderValue::try_from(names.join(", ")).ok()
+    }
+}
+
+impl Debug for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<&str> = self.codecs.iter().map(|(name, _)| name.as_str()).collect();
+        f.debug_struct("Compression")
+            .field("codecs", &names)
+            .field("encode_requests", &self.encode_requests)
+            .field("max_decoded_size", &self.max_decoded_size)
+            .finish()
+    }
+}
+
+#[async_trait]
+impl Middleware for Compression {
+    async fn handle(&self, request: &mut Request, next: Next<'_>) -> Result<Response> {
+        if !request.headers().contains_key(header::ACCEPT_ENCODING) {
+            if let Some(accept) = self.accept_encoding() {
+                request.insert_header(header::ACCEPT_ENCODING, accept);
+            }
+        }
+        if let Some(name) = &self.encode_requests {
+            let streaming = request.extensions().get::<Streaming>().is_some();
+            if !streaming && !request.headers().contains_key(header::CONTENT_ENCODING) {
+                encode(request, name, self.get(name)).await?;
+            }
+        }
+
+        let mut response = next.run(request).await?;
+        let codings: Vec<String> = match response.headers().get(header::CONTENT_ENCODING) {
+            Some(value) => value
+                .to_str()
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|coding| !coding.is_empty() && !coding.eq_ignore_ascii_case("identity"))
+                .map(str::to_owned)
+                .collect(),
+            None => return Ok(response),
+        };
+        // Codings are listed in the order they were applied, so they're undone from the last.
+        let Some(codecs) = codings
+            .iter()
+            .rev()
+            .map(|coding| self.get(coding))
+            .collect::<Option<Vec<_>>>()
+        else {
+            return Ok(response);
+        };
+        if codecs.is_empty() {
+            return Ok(response);
+        }
+
+        let body = response.into_bytes().await?;
+        if body.is_empty() {
+            response.replace_body(body);
+            return Ok(response);
+        }
+        let mut body = body.to_vec();
+        for codec in codecs {
+            body = codec
+                .decode(&body, self.max_decoded_size)
+                .map_err(Error::Io)?;
+            if body.len() > self.max_decoded_size {
+                let error = io::Error::new(io::ErrorKind::InvalidData, "decoded body is too large");
+                return Err(Error::Io(error));
+            }
+            if body.is_empty() {
+                break;
+            }
+        }
+        response.headers_mut().remove(header::CONTENT_ENCODING);
+        response.headers_mut().remove(header::CONTENT_LENGTH);
+        response.replace_body(bytes::Bytes::from(body));
+        Ok(response)
+    }
+}
+
+async fn encode(request: &mut Request, name: &str, codec: Option<&dyn Codec>) -> Result<()> {
+    let codec = codec.ok_or_else(|| Error::UnsupportedEncoding(name.to_owned()))?;
+    let body = request.into_bytes().await?;
+    if body.is_empty() {
+        request.replace_body(body);
+        return Ok(());
+    }
+    let encoded = codec.encode(&body).map_err(Error::Io)?;
+    let name = HeaderValue::try_from(name).map_err(|error| Error::InvalidHeader(error.into()))?;
+    request.insert_header(header::CONTENT_ENCODING, name);
+    request.headers_mut().remove(header::CONTENT_LENGTH);
+    request.replace_body(bytes::Bytes::from(encoded));
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Codec, Compression};
+    use crate::{Error, Middleware, Next};
+    use async_trait::async_trait;
+    use bytes::Bytes;
+    use http_kit::header::{self, HeaderValue};
+    use http_kit::{Body, Endpoint, Method, Request, Response};
+    use hyper::http;
+    use std::io;
+
+    /// Writes every byte twice. Like real codecs, it can't decode empty input.
+    struct Double;
+
+    impl Codec for Double {
+        fn encode(&self, data: &[u8]) -> io::Result<Vec<u8>> {
+            Ok(data.iter().flat_map(|&byte| [byte, byte]).collect())
+        }
+
+        fn decode(&self, data: &[u8], limit: usize) -> io::Result<Vec<u8>> {
+            if data.is_empty() || !data.len().is_multiple_of(2) {
+                return Err(io::ErrorKind::InvalidData.into());
+            }
+            if data.len() / 2 > limit {
+                return Err(io::ErrorKind::OutOfMemory.into());
+            }
+            Ok(data.iter().step_by(2).copied().collect())
+        }
+    }
+
+    /// Answers with the request body and its `Content-Encoding`, plus the `Accept-Encoding` it
+    /// was sent in `x-accept-encoding`.
+    struct Echo;
+
+    #[async_trait]
+    impl Endpoint for Echo {
+        async fn call_endpoint(&self, request: &mut Request) -> http_kit::Result<Response> {
+            let mut response = http::Response::builder();
+            for (from, to) in [
+                (header::CONTENT_ENCODING, header::CONTENT_ENCODING),
+                (
+                    header::ACCEPT_ENCODING,
+                    "x-accept-encoding".parse().unwrap(),
+                ),
+            ] {
+                if let Some(value) = request.headers().get(from) {
+                    response = response.header(to, value);
+                }
+            }
+            let body = request.into_bytes().await?;
+            Ok(response.body(Body::from(body)).unwrap().into())
+        }
+    }
+
+    async fn send(compression: &Compression, request: &mut Request) -> crate::Result<Response> {
+        compression.handle(request, Next::new(&[], &Echo)).await
+    }
+
+    fn request(body: &'static str, encoding: Option<&'static str>) -> Request {
+        let mut request = Request::new(Method::POST, "http://example.com/");
+        request.replace_body(Bytes::from(body));
+        if let Some(encoding) = encoding {
+            request.insert_header(header::CONTENT_ENCODING, HeaderValue::from_static(encoding));
+        }
+        request
+    }
+
+    #[tokio::test]
+    async fn decode_responses() {
+        let compression = Compression::new().codec("X-Double", Double);
+        let mut response = send(&compression, &mut request("hheelllloo", Some("x-double")))
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["x-accept-encoding"], "x-double");
+        assert!(!response.headers().contains_key(header::CONTENT_ENCODING));
+        assert_eq!(response.into_bytes().await.unwrap(), "hello");
+
+        // Unknown codings are left alone.
+        let mut response = send(&compression, &mut request("hheelllloo", Some("gzip")))
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "gzip");
+        assert_eq!(response.into_bytes().await.unwrap(), "hheelllloo");
+    }
+
+    #[tokio::test]
+    async fn empty_bodies_arent_decoded() {
+        let compression = Compression::new().codec("x-double", Double);
+        let mut response = send(&compression, &mut request("", Some("x-double")))
+            .await
+            .unwrap();
+        assert_eq!(response.headers()[header::CONTENT_ENCODING], "x-double");
+        assert!(response.into_bytes().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn decoded_size_is_limited() {
+        let compression = Compression::new()
+            .codec("x-double", Double)
+            .max_decoded_size(4);
+        let result = send(&compression, &mut request("hheelllloo", Some("x-double"))).await;
+        assert!(matches!(result, Err(Error::Io(_))));
+    }
+
+    #[tokio::test]
+    async fn encode_requests() {
+        let compression = Compression::new()
+            .codec("x-double", Double)
+            .encode_requests("x-double");
+        let mut response = send(&compression, &mut request("hi", None)).await.unwrap();
+        // The echoed body is decoded again on the way back.
+        assert_eq!(response.into_bytes().await.unwrap(), "hi");
+
+        let compression = Compression::new().encode_requests("x-double");
+        let result = send(&compression, &mut request("hi", None)).await;
+        assert!(matches!(result, Err(Error::UnsupportedEncoding(name)) if name == "x-double"));
+    }
+}
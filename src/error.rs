@@ -0,0 +1,89 @@
+use hyper::http;
+use std::fmt::{self, Display};
+
+/// Errors produced while sending a request.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The request URI couldn't be parsed.
+    InvalidUri(http::Error),
+    /// The request URL isn't a valid RFC 3986 URL.
+    InvalidUrl(crate::UrlError),
+    /// A header generated by the client wasn't a valid header value.
+    InvalidHeader(http::Error),
+    /// The backend failed to produce a response.
+    Backend(http_kit::Error),
+    /// The request was cancelled through an [`AbortHandle`](crate::AbortHandle).
+    Aborted,
+    /// An [`Archive`](crate::Archive) was written by a newer release of this crate.
+    UnsupportedArchive(u32),
+    /// No decoder is registered for the `Content-Type` of the response.
+    UnsupportedMediaType(String),
+    /// [`Compression::encode_requests`](crate::encoding::Compression::encode_requests) names a
+    /// content coding no codec is registered for.
+    UnsupportedEncoding(String),
+    /// The response body couldn't be decoded.
+    Decode(Box<dyn std::error::Error + Send + Sync>),
+    /// Reading or writing a local file failed, such as the log of
+    /// [`Audit`](crate::audit::Audit).
+    Io(std::io::Error),
+    /// A response repeated a header that [`HeaderPolicy`](crate::headers::HeaderPolicy) only
+    /// allows once.
+    DuplicateHeader(http_kit::header::HeaderName),
+    /// No response arrived within the [timeout](crate::Client::timeout) of the client.
+    Timeout,
+    /// A language given to [`Language`](crate::language::Language) isn't a language tag or
+    /// `*`. The language is empty when none were given at all.
+    InvalidLanguage(String),
+    /// The environment variable of this name, read by
+    /// [`Client::from_env`](crate::Client::from_env), is malformed.
+    InvalidEnv(&'static str),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidUri(error) => write!(f, "invalid uri: {error}"),
+            Error::InvalidUrl(error) => write!(f, "invalid url: {error}"),
+            Error::InvalidHeader(error) => write!(f, "invalid header: {error}"),
+            Error::Backend(error) => Display::fmt(error, f),
+            Error::Aborted => f.write_str("request aborted"),
+            Error::UnsupportedArchive(version) => {
+                write!(f, "unsupported archive version {version}")
+            }
+            Error::UnsupportedMediaType(media_type) => {
+                write!(f, "no decoder for media type `{media_type}`")
+            }
+            Error::UnsupportedEncoding(name) => write!(f, "no codec for content coding `{name}`"),
+            Error::Decode(error) => write!(f, "failed to decode response: {error}"),
+            Error::Io(error) => write!(f, "i/o error: {error}"),
+            Error::DuplicateHeader(name) => write!(f, "duplicate `{name}` header"),
+            Error::Timeout => f.write_str("request timed out"),
+            Error::InvalidLanguage(language) if language.is_empty() => {
+                f.write_str("no languages given")
+            }
+            Error::InvalidLanguage(language) => write!(f, "invalid language range `{language}`"),
+            Error::InvalidEnv(name) => write!(f, "invalid value for environment variable {name}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::InvalidUri(error) | Error::InvalidHeader(error) => Some(error),
+            Error::InvalidUrl(error) => Some(error),
+            Error::Decode(error) => Some(error.as_ref()),
+            Error::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<http_kit::Error> for Error {
+    fn from(error: http_kit::Error) -> Self {
+        Error::Backend(error)
+    }
+}
@@ -0,0 +1,288 @@
+//! Repeated response headers.
+
+use crate::{Error, Middleware, Next, Result};
+use async_trait::async_trait;
+use http_kit::header::{self, HeaderMap, HeaderName, HeaderValue};
+use http_kit::{Request, Response};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// What to make of a header a response repeats.
+///
+/// [`HeaderMap`] keeps every value, but `get` silently returns the first one, while servers
+/// and proxies disagree on whether the first or last occurrence wins.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Duplicates {
+    /// Keep the first value.
+    First,
+    /// Keep the last value.
+    Last,
+    /// Join the values into one comma-separated list, as RFC 9110 allows for list headers.
+    Join,
+    /// Treat a repeated header as an error.
+    Error,
+}
+
+/// Middleware collapsing repeated response headers into one value.
+///
+/// Each header follows its own [`Duplicates`] policy, or the default policy if one was set;
+/// other headers keep every value. `Set-Cookie` can't be joined and always keeps every value.
+/// A header repeated under the [`Duplicates::Error`] policy fails the request with
+/// [`Error::DuplicateHeader`].
+#[derive(Debug, Clone, Default)]
+pub struct HeaderPolicy {
+    headers: HashMap<HeaderName, Duplicates>,
+    default: Option<Duplicates>,
+}
+
+impl HeaderPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resolve repeats of the header `name` with `policy`.
+    pub fn header(mut self, name: HeaderName, policy: Duplicates) -> Self {
+        self.headers.insert(name, policy);
+        self
+    }
+
+    /// Resolve repeats of every header without its own policy with `policy`.
+    pub fn default_policy(mut self, policy: Duplicates) -> Self {
+        self.default = Some(policy);
+        self
+    }
+
+    fn apply(&self, headers: &mut HeaderMap) -> Result<()> {
+        let repeated: Vec<HeaderName> = headers
+            .keys()
+            .filter(|name| {
+                *name != header::SET_COOKIE && headers.get_all(*name).iter().nth(1).is_some()
+            })
+            .cloned()
+            .collect();
+        for name in repeated {
+            let Some(policy) = self.headers.get(&name).copied().or(self.default) else {
+                continue;
+            };
+            let mut values = headers.get_all(&name).iter();
+            let value = match policy {
+                Duplicates::First => values.next().cloned(),
+                Duplicates::Last => values.last().cloned(),
+                Duplicates::Join => {
+                    let joined: Vec<&[u8]> = values.map(HeaderValue::as_bytes).collect();
+                    HeaderValue::from_bytes(&joined.join(&b", "[..])).ok()
+                }
+                Duplicates::Error => return Err(Error::DuplicateHeader(name)),
+            };
+            if let Some(value) = value {
+                headers.insert(name, value);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Middleware for HeaderPolicy {
+    async fn handle(&self, request: &mut Request, next: Next<'_>) -> Result<Response> {
+        let mut response = next.run(request).await?;
+        self.apply(response.headers_mut())?;
+        Ok(response)
+    }
+}
+
+/// Headers whose value is a comma-separated list (RFC 9110, section 5.6.1).
+///
+/// `WWW-Authenticate` and `Proxy-Authenticate` are lists as well, but their challenges contain
+/// commas of their own, so they're kept whole.
+static LISTS: [HeaderName; 27] = [
+    header::ACCEPT,
+    header::ACCEPT_CHARSET,
+    header::ACCEPT_ENCODING,
+    header::ACCEPT_LANGUAGE,
+    header::ACCEPT_RANGES,
+    header::ACCESS_CONTROL_ALLOW_HEADERS,
+    header::ACCESS_CONTROL_ALLOW_METHODS,
+    header::ACCESS_CONTROL_EXPOSE_HEADERS,
+    header::ACCESS_CONTROL_REQUEST_HEADERS,
+    header::ALLOW,
+    header::CACHE_CONTROL,
+    header::CONNECTION,
+    header::CONTENT_ENCODING,
+    header::CONTENT_LANGUAGE,
+    header::EXPECT,
+    header::FORWARDED,
+    header::IF_MATCH,
+    header::IF_NONE_MATCH,
+    header::LINK,
+    header::PRAGMA,
+    header::TE,
+    header::TRAILER,
+    header::TRANSFER_ENCODING,
+    header::UPGRADE,
+    header::VARY,
+    header::VIA,
+    header::WARNING,
+];
+
+pub(crate) fn values<'a>(headers: &'a HeaderMap, name: &HeaderName) -> Vec<&'a str> {
+    let values = headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok());
+    if !LISTS.contains(name) {
+        return values.map(str::trim).collect();
+    }
+    values
+        .flat_map(split_list)
+        .map(str::trim)
+        .filter(|value| !value.is_empty())
+        .collect()
+}
+
+/// Split a list at the commas outside of quoted strings and `<...>` URI references, as in
+/// `Link: <https://a/?x=1,2>; title="a, b"`.
+fn split_list(value: &str) -> Vec<&str> {
+    let mut elements = Vec::new();
+    let (mut start, mut quoted, mut escaped, mut bracketed) = (0, false, false, false);
+    for (index, c) in value.char_indices() {
+        if quoted {
+            match c {
+                _ if escaped => escaped = false,
+                '\\' => escaped = true,
+                '"' => quoted = false,
+                _ => {}
+            }
+            continue;
+        }
+        match c {
+            '"' => quoted = true,
+            '<' => bracketed = true,
+            '>' => bracketed = false,
+            ',' if !bracketed => {
+                elements.push(&value[start..index]);
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+    elements.push(&value[start..]);
+    elements
+}
+
+pub(crate) fn resolve<'a>(
+    headers: &'a HeaderMap,
+    name: &HeaderName,
+    policy: Duplicates,
+) -> Result<Option<Cow<'a, str>>> {
+    let mut values = headers
+        .get_all(name)
+        .iter()
+        .filter_map(|value| value.to_str().ok());
+    Ok(match policy {
+        Duplicates::First => values.next().map(Cow::Borrowed),
+        Duplicates::Last => values.last().map(Cow::Borrowed),
+        Duplicates::Join => {
+            let values: Vec<&str> = values.collect();
+            match values.as_slice() {
+                [] => None,
+                [value] => Some(Cow::Borrowed(*value)),
+                _ => Some(Cow::Owned(values.join(", "))),
+            }
+        }
+        Duplicates::Error => {
+            let first = values.next();
+            if values.next().is_some() {
+                return Err(Error::DuplicateHeader(name.clone()));
+            }
+            first.map(Cow::Borrowed)
+        }
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::{resolve, values, Duplicates, HeaderPolicy};
+    use http_kit::header::{self, HeaderMap, HeaderValue};
+
+    fn headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in [
+            (header::CACHE_CONTROL, "no-cache"),
+            (header::CACHE_CONTROL, "max-age=0, private"),
+            (
+                header::SET_COOKIE,
+                "a=1; Expires=Wed, 21 Oct 2099 07:28:00 GMT",
+            ),
+            (header::SET_COOKIE, "b=2"),
+            (header::DATE, "Wed, 21 Oct 2015 07:28:00 GMT"),
+            (
+                header::LINK,
+                r#"<https://example.com/a,b>; rel="next"; title="a, \"b\", c", </prev>; rel=prev"#,
+            ),
+            (
+                header::WWW_AUTHENTICATE,
+                r#"Basic realm="a", charset="UTF-8""#,
+            ),
+        ] {
+            headers.append(name, HeaderValue::from_static(value));
+        }
+        headers
+    }
+
+    #[test]
+    fn accessors() {
+        let headers = headers();
+        assert_eq!(
+            values(&headers, &header::CACHE_CONTROL),
+            ["no-cache", "max-age=0", "private"]
+        );
+        assert_eq!(values(&headers, &header::SET_COOKIE).len(), 2);
+        assert_eq!(
+            values(&headers, &header::DATE),
+            ["Wed, 21 Oct 2015 07:28:00 GMT"]
+        );
+        assert_eq!(
+            values(&headers, &header::LINK),
+            [
+                r#"<https://example.com/a,b>; rel="next"; title="a, \"b\", c""#,
+                "</prev>; rel=prev"
+            ]
+        );
+        assert_eq!(
+            values(&headers, &header::WWW_AUTHENTICATE),
+            [r#"Basic realm="a", charset="UTF-8""#]
+        );
+        let last = resolve(&headers, &header::CACHE_CONTROL, Duplicates::Last).unwrap();
+        assert_eq!(last.as_deref(), Some("max-age=0, private"));
+        assert!(resolve(&headers, &header::CACHE_CONTROL, Duplicates::Error).is_err());
+        assert!(resolve(&headers, &header::ETAG, Duplicates::Error)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn policy() {
+        let mut headers = headers();
+        HeaderPolicy::new()
+            .default_policy(Duplicates::Join)
+            .apply(&mut headers)
+            .unwrap();
+        assert_eq!(
+            headers.get_all(header::CACHE_CONTROL).iter().count(),
+            1,
+            "joined into one value"
+        );
+        assert_eq!(
+            headers[header::CACHE_CONTROL],
+            "no-cache, max-age=0, private"
+        );
+        assert_eq!(headers.get_all(header::SET_COOKIE).iter().count(), 2);
+
+        let mut headers = self::headers();
+        let error = HeaderPolicy::new()
+            .header(header::CACHE_CONTROL, Duplicates::Error)
+            .apply(&mut headers);
+        assert!(error.is_err());
+    }
+}
@@ -0,0 +1,147 @@
+//! Idempotency keys for unsafe requests.
+
+use crate::{Middleware, Next, Result};
+use async_trait::async_trait;
+use http_kit::header::{HeaderName, HeaderValue};
+use http_kit::{Request, Response};
+
+/// The key [`Idempotency`] attached to a request.
+///
+/// It's stored in the extensions of both the request and its response, so a caller that
+/// retries by hand can send the same key again.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct IdempotencyKey(pub HeaderValue);
+
+/// Middleware attaching an `Idempotency-Key` header to requests with unsafe methods.
+///
+/// A key already present in the request extensions or headers is reused rather than replaced,
+/// so middleware further down the stack that retries the request keeps sending the same key.
+#[derive(Debug, Clone)]
+pub struct Idempotency {
+    header: HeaderName,
+}
+
+impl Idempotency {
+    pub fn new() -> Self {
+        Self::with_header(HeaderName::from_static("idempotency-key"))
+    }
+
+    /// Send the key in the `header` header instead of `Idempotency-Key`.
+    pub fn with_header(header: HeaderName) -> Self {
+        Self { header }
+    }
+}
+
+impl Default for Idempotency {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl Middleware for Idempotency {
+    async fn handle(&self, request: &mut Request, next: Next<'_>) -> Result<Response> {
+        if request.method().is_safe() {
+            return next.run(request).await;
+        }
+
+        let key = match request.extensions().get::<IdempotencyKey>() {
+            Some(key) => key.clone(),
+            None => match request.headers().get(&self.header) {
+                Some(value) => IdempotencyKey(value.clone()),
+                None => IdempotencyKey(generate()),
+            },
+        };
+        request.insert_header(self.header.clone(), key.0.clone());
+        request.extensions_mut().insert(key.clone());
+
+        let mut response = next.run(request).await?;
+        response.extensions_mut().insert(key);
+        Ok(response)
+    }
+}
+
+fn generate() -> HeaderValue {
+    let key = format!("{:032x}", fastrand::u128(..));
+    HeaderValue::try_from(key).expect("hex digits are a valid header value")
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Idempotency, IdempotencyKey};
+    use crate::middleware::Capture;
+    use crate::{Middleware, Next};
+    use http_kit::header::{HeaderName, HeaderValue};
+    use http_kit::{Method, Request, Response};
+
+    async fn send(idempotency: &Idempotency, request: &mut Request) -> (Request, Response) {
+        let capture = Capture::default();
+        let response = idempotency
+            .handle(request, Next::new(&[], &capture))
+            .await
+            .unwrap();
+        (capture.into_request().unwrap(), response)
+    }
+
+    fn key(request: &Request) -> Option<&HeaderValue> {
+        request.headers().get("idempotency-key")
+    }
+
+    #[tokio::test]
+    async fn generated_keys() {
+        let idempotency = Idempotency::new();
+        let (first, response) = send(&idempotency, &mut Request::new(Method::POST, "/")).await;
+        let (second, _) = send(&idempotency, &mut Request::new(Method::POST, "/")).await;
+
+        let generated = key(&first).unwrap();
+        assert_eq!(generated.len(), 32);
+        assert!(generated.as_bytes().iter().all(u8::is_ascii_hexdigit));
+        assert_ne!(key(&second), Some(generated));
+        assert_eq!(
+            first.extensions().get::<IdempotencyKey>(),
+            Some(&IdempotencyKey(generated.clone()))
+        );
+        assert_eq!(
+            response.extensions().get::<IdempotencyKey>(),
+            Some(&IdempotencyKey(generated.clone()))
+        );
+    }
+
+    #[tokio::test]
+    async fn keys_are_reused() {
+        let idempotency = Idempotency::new();
+        let (_, response) = send(&idempotency, &mut Request::new(Method::POST, "/")).await;
+        let original = response
+            .extensions()
+            .get::<IdempotencyKey>()
+            .unwrap()
+            .clone();
+
+        // A replay carrying the key of the first attempt sends it again.
+        let mut replay = Request::new(Method::POST, "/");
+        replay.extensions_mut().insert(original.clone());
+        let (replay, _) = send(&idempotency, &mut replay).await;
+        assert_eq!(key(&replay), Some(&original.0));
+
+        // So does a request that already has the header.
+        let mut request = Request::new(Method::PUT, "/");
+        request.insert_header(
+            HeaderName::from_static("idempotency-key"),
+            HeaderValue::from_static("chosen"),
+        );
+        let (request, _) = send(&idempotency, &mut request).await;
+        assert_eq!(key(&request).unwrap(), "chosen");
+    }
+
+    #[tokio::test]
+    async fn safe_methods_are_skipped() {
+        let idempotency = Idempotency::with_header(HeaderName::from_static("x-request-key"));
+        for method in [Method::GET, Method::HEAD, Method::OPTIONS, Method::TRACE] {
+            let (request, response) = send(&idempotency, &mut Request::new(method, "/")).await;
+            assert!(!request.headers().contains_key("x-request-key"));
+            assert!(response.extensions().get::<IdempotencyKey>().is_none());
+        }
+        let (request, _) = send(&idempotency, &mut Request::new(Method::DELETE, "/")).await;
+        assert!(request.headers().contains_key("x-request-key"));
+    }
+}
@@ -0,0 +1,195 @@
+use http_kit::header::{self, HeaderName, HeaderValue};
+use hyper::http;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{PoisonError, RwLock};
+
+/// How many distinct names and values each client keeps. Past that, the least recently used
+/// entry makes room for a new one, so unique values such as request ids can't grow the table
+/// forever.
+const CAPACITY: usize = 1024;
+
+/// Parts of header names that suggest credentials, whose values are never cached.
+const SECRETS: [&str; 6] = ["auth", "cookie", "key", "password", "secret", "token"];
+
+/// Header names and values parsed once per client and shared by every request afterwards.
+///
+/// Values of credential headers such as `Authorization` or `X-Api-Key` aren't kept, so tokens
+/// don't outlive the requests they were sent with; they're marked sensitive instead.
+#[derive(Debug, Default)]
+pub(crate) struct Interner {
+    names: Table<HeaderName>,
+    values: Table<HeaderValue>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+#[derive(Debug)]
+struct Table<T> {
+    entries: RwLock<HashMap<Box<str>, Entry<T>>>,
+    clock: AtomicU64,
+}
+
+#[derive(Debug)]
+struct Entry<T> {
+    value: T,
+    /// The `clock` of the table when the entry was last used.
+    used: AtomicU64,
+}
+
+impl<T> Default for Table<T> {
+    fn default() -> Self {
+        Self {
+            entries: RwLock::default(),
+            clock: AtomicU64::new(0),
+        }
+    }
+}
+
+impl<T: Clone> Table<T> {
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn get(&self, key: &str) -> Option<T> {
+        let entries = self.entries.read().unwrap_or_else(PoisonError::into_inner);
+        let entry = entries.get(key)?;
+        entry.used.store(self.tick(), Ordering::Relaxed);
+        Some(entry.value.clone())
+    }
+
+    fn insert(&self, key: &str, value: T) {
+        let mut entries = self.entries.write().unwrap_or_else(PoisonError::into_inner);
+        if entries.len() >= CAPACITY && !entries.contains_key(key) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used.load(Ordering::Relaxed))
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        let used = AtomicU64::new(self.tick());
+        entries.insert(key.into(), Entry { value, used });
+    }
+
+    #[cfg(test)]
+    fn contains(&self, key: &str) -> bool {
+        let entries = self.entries.read().unwrap_or_else(PoisonError::into_inner);
+        entries.contains_key(key)
+    }
+}
+
+impl Interner {
+    pub fn name(&self, name: &str) -> Result<HeaderName, http::Error> {
+        self.intern(&self.names, name, |name| Ok(HeaderName::try_from(name)?))
+    }
+
+    /// The value of the header `name`, cached unless it looks like a credential.
+    pub fn value(&self, name: &HeaderName, value: &str) -> Result<HeaderValue, http::Error> {
+        if is_sensitive(name) {
+            let mut value = HeaderValue::try_from(value)?;
+            value.set_sensitive(true);
+            return Ok(value);
+        }
+        self.intern(&self.values, value, |value| {
+            Ok(HeaderValue::try_from(value)?)
+        })
+    }
+
+    fn intern<T: Clone>(
+        &self,
+        table: &Table<T>,
+        key: &str,
+        parse: impl FnOnce(&str) -> Result<T, http::Error>,
+    ) -> Result<T, http::Error> {
+        if let Some(interned) = table.get(key) {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+            return Ok(interned);
+        }
+        self.misses.fetch_add(1, Ordering::Relaxed);
+        let parsed = parse(key)?;
+        table.insert(key, parsed.clone());
+        Ok(parsed)
+    }
+
+    pub fn stats(&self) -> InternStats {
+        InternStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// Whether the header `name` looks like it carries credentials.
+pub(crate) fn is_sensitive(name: &HeaderName) -> bool {
+    *name == header::AUTHORIZATION
+        || *name == header::PROXY_AUTHORIZATION
+        || SECRETS.iter().any(|part| name.as_str().contains(part))
+}
+
+/// How often [`RequestBuilder::header`](crate::RequestBuilder::header) reused a header name or
+/// value parsed for an earlier request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct InternStats {
+    pub hits: u64,
+    pub misses: u64,
+}
+
+impl InternStats {
+    /// The share of lookups served from the cache, or zero before the first one.
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Interner, CAPACITY};
+    use http_kit::header::{self, HeaderName};
+
+    #[test]
+    fn reuse() {
+        let interner = Interner::default();
+        for _ in 0..3 {
+            interner.name("x-request-kind").unwrap();
+            interner.value(&header::ACCEPT, "text/html").unwrap();
+        }
+        assert!(interner.name("bad name").is_err());
+
+        let stats = interner.stats();
+        assert_eq!((stats.hits, stats.misses), (4, 3));
+        assert!((stats.hit_rate() - 4.0 / 7.0).abs() < f64::EPSILON);
+    }
+
+    #[test]
+    fn credentials_arent_cached() {
+        let interner = Interner::default();
+        let api_key = HeaderName::from_static("x-api-key");
+        for name in [header::AUTHORIZATION, header::COOKIE, api_key] {
+            let value = interner.value(&name, "secret").unwrap();
+            assert!(value.is_sensitive());
+        }
+        assert!(!interner.values.contains("secret"));
+        assert_eq!(interner.stats().misses, 0);
+    }
+
+    #[test]
+    fn least_recently_used_is_evicted() {
+        let interner = Interner::default();
+        for index in 0..CAPACITY {
+            interner.value(&header::ACCEPT, &index.to_string()).unwrap();
+        }
+        interner.value(&header::ACCEPT, "0").unwrap();
+        interner.value(&header::ACCEPT, "new").unwrap();
+
+        assert!(interner.values.contains("0"), "used recently");
+        assert!(!interner.values.contains("1"), "least recently used");
+        assert!(interner.values.contains("new"));
+    }
+}
@@ -0,0 +1,164 @@
+//! Language negotiation.
+
+use crate::{Error, Middleware, Next, Result};
+use async_trait::async_trait;
+use http_kit::header::{self, HeaderValue};
+use http_kit::{Request, Response};
+use std::fmt::{self, Display};
+use std::hash::{Hash, Hasher};
+
+/// A language tag such as `en` or `pt-BR` (RFC 5646), compared without regard to case.
+#[derive(Debug, Clone)]
+pub struct LanguageTag(String);
+
+impl LanguageTag {
+    /// Parse a tag, checking only its syntax: subtags of one to eight letters and digits.
+    pub fn parse(tag: &str) -> Option<Self> {
+        let valid = tag.split('-').all(|subtag| {
+            (1..=8).contains(&subtag.len()) && subtag.bytes().all(|b| b.is_ascii_alphanumeric())
+        });
+        valid.then(|| Self(tag.to_owned()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The primary language subtag, e.g. `pt` for `pt-BR`.
+    pub fn primary(&self) -> &str {
+        self.0.split('-').next().unwrap_or_default()
+    }
+}
+
+impl PartialEq for LanguageTag {
+    fn eq(&self, other: &Self) -> bool {
+        self.0.eq_ignore_ascii_case(&other.0)
+    }
+}
+
+impl Eq for LanguageTag {}
+
+impl Hash for LanguageTag {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.to_ascii_lowercase().hash(state);
+    }
+}
+
+impl Display for LanguageTag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Middleware sending the client's language preferences in `Accept-Language`.
+///
+/// Languages listed first are preferred, with quality values decreasing in steps of `0.1`. A
+/// request that already has an `Accept-Language` header keeps it, so a single request overrides
+/// the client's preferences with [`RequestBuilder::language`](crate::RequestBuilder::language).
+/// Read the language the server chose with
+/// [`ResponseExt::content_language`](crate::ResponseExt::content_language).
+#[derive(Debug, Clone)]
+pub struct Language {
+    pub(crate) header: HeaderValue,
+}
+
+impl Language {
+    /// Prefer `languages` in order, each a language tag or `*` for any other language.
+    ///
+    /// Fails with [`Error::InvalidLanguage`] if one isn't a valid language range or if there
+    /// are none at all.
+    pub fn new<'a>(languages: impl IntoIterator<Item = &'a str>) -> Result<Self> {
+        let mut ranges = Vec::new();
+        for (index, language) in (0u32..).zip(languages) {
+            if language != "*" && LanguageTag::parse(language).is_none() {
+                return Err(Error::InvalidLanguage(language.to_owned()));
+            }
+            ranges.push(match 10u32.saturating_sub(index).max(1) {
+                10 => language.to_owned(),
+                quality => format!("{language};q=0.{quality}"),
+            });
+        }
+        if ranges.is_empty() {
+            return Err(Error::InvalidLanguage(String::new()));
+        }
+        let header = HeaderValue::try_from(ranges.join(", "))
+            .map_err(|error| Error::InvalidHeader(error.into()))?;
+        Ok(Self { header })
+    }
+}
+
+#[async_trait]
+impl Middleware for Language {
+    async fn handle(&self, request: &mut Request, next: Next<'_>) -> Result<Response> {
+        if !request.headers().contains_key(header::ACCEPT_LANGUAGE) {
+            request.insert_header(header::ACCEPT_LANGUAGE, self.header.clone());
+        }
+        next.run(request).await
+    }
+}
+
+/// The languages of a `Content-Language` header, skipping malformed tags.
+pub(crate) fn content_language(response: &Response) -> Vec<LanguageTag> {
+    crate::headers::values(response.headers(), &header::CONTENT_LANGUAGE)
+        .into_iter()
+        .filter_map(LanguageTag::parse)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Language, LanguageTag};
+    use crate::{Client, Error};
+    use http_kit::header;
+
+    #[test]
+    fn preferences() {
+        let language = Language::new(["fr-CH", "fr", "en"]).unwrap();
+        assert_eq!(language.header, "fr-CH, fr;q=0.9, en;q=0.8");
+        let language = Language::new(["de", "*"]).unwrap();
+        assert_eq!(language.header, "de, *;q=0.9");
+        let invalid: [&[&str]; 5] = [
+            &["en\n"],
+            &["en-"],
+            &["en", "fr;q=0.5"],
+            &["toolonglanguage"],
+            &[],
+        ];
+        for languages in invalid {
+            assert!(matches!(
+                Language::new(languages.iter().copied()),
+                Err(Error::InvalidLanguage(_))
+            ));
+        }
+
+        let tag = LanguageTag::parse("pt-BR").unwrap();
+        assert_eq!(tag, LanguageTag::parse("PT-br").unwrap());
+        assert_eq!(tag.primary(), "pt");
+        assert!(LanguageTag::parse("not a tag").is_none());
+    }
+
+    #[tokio::test]
+    async fn per_request_languages() {
+        let client = Client::new().middleware(Language::new(["fr", "en"]).unwrap());
+        let request = client.get("http://example.com/").dry_run().await.unwrap();
+        assert_eq!(request.headers()[header::ACCEPT_LANGUAGE], "fr, en;q=0.9");
+
+        let request = client
+            .get("http://example.com/")
+            .language(["de-CH", "de"])
+            .dry_run()
+            .await
+            .unwrap();
+        assert_eq!(
+            request.headers()[header::ACCEPT_LANGUAGE],
+            "de-CH, de;q=0.9"
+        );
+
+        let result = client
+            .get("http://example.com/")
+            .language(["de_CH"])
+            .dry_run()
+            .await;
+        assert!(matches!(result, Err(Error::InvalidLanguage(language)) if language == "de_CH"));
+    }
+}
@@ -1,38 +1,128 @@
+mod abort;
+mod archive;
+#[cfg(feature = "audit")]
+pub mod audit;
 pub mod backend;
+mod batch;
+mod body;
+mod config;
+mod cookie_store;
+mod decode;
+pub mod deprecation;
+pub mod diagnose;
+pub mod encoding;
+mod error;
+pub mod headers;
+pub mod idempotency;
+mod intern;
+pub mod language;
+pub mod limit;
+#[cfg(feature = "tracing")]
+pub mod logging;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+mod middleware;
+pub mod mirror;
+mod observer;
+mod overrides;
+mod response;
+pub mod resume;
+mod scope;
+#[cfg(feature = "test-util")]
+pub mod test_util;
+mod uri;
+pub mod versioning;
+pub use abort::AbortHandle;
+pub use archive::Archive;
 pub use backend::ClientBackend;
 use backend::HyperBackend;
+pub use batch::Batch;
+pub use body::{BodySender, Streaming};
+pub use config::Config;
+use cookie_store::CookieStore;
+use decode::Decoders;
+pub use decode::{Decoder, Sink};
+pub use error::{Error, Result};
+pub use intern::InternStats;
+use intern::Interner;
+use middleware::{Capture, Middlewares};
+pub use middleware::{DryRun, Middleware, Next};
+pub use observer::ClientObserver;
+use observer::Observers;
+pub use overrides::Overrides;
+pub use response::{RequestInfo, ResponseExt};
+pub use scope::RequestScope;
+pub use uri::{IntoUri, UrlError, UrlErrorKind, UrlParser};
 
-use cookie::{Cookie, CookieJar};
+use cookie::Cookie;
 use http::HeaderValue;
-use http_kit::{header, Method, Request, Response, Uri};
+use http_kit::{header, Body, Endpoint, Method, Request, Response, Uri};
 use hyper::http;
 use once_cell::sync::Lazy;
-use std::fmt::Debug;
 use std::future::{Future, IntoFuture};
+use std::mem::replace;
 use std::ops::{Deref, DerefMut};
 use std::pin::Pin;
-use std::sync::RwLock;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
 type DefaultBackend = HyperBackend;
 
 #[derive(Debug, Default)]
 pub struct Client<B = DefaultBackend> {
-    cookies: RwLock<CookieJar>,
+    cookies: RwLock<CookieStore>,
     cookie_store: bool,
+    decoders: Decoders,
+    headers: Interner,
+    config: RwLock<Arc<Config>>,
+    urls: Option<UrlParser>,
+    observers: Observers,
+    middlewares: Middlewares,
     backend: B,
 }
 
 impl<B: ClientBackend> Client<B> {
-    pub fn method<U>(&self, method: Method, uri: U) -> RequestBuilder<B>
-    where
-        U: TryInto<Uri>,
-        U::Error: Debug,
-    {
-        RequestBuilder::new(Request::new(method, uri.try_into().unwrap()), self)
+    /// Create a client that sends its requests through `backend`.
+    pub fn with_backend(backend: B) -> Self {
+        Self {
+            cookies: RwLock::default(),
+            cookie_store: false,
+            decoders: Decoders::default(),
+            headers: Interner::default(),
+            config: RwLock::default(),
+            urls: None,
+            observers: Observers::default(),
+            middlewares: Middlewares::default(),
+            backend,
+        }
     }
 
-    pub fn cookie(self, cookie: Cookie<'static>) -> Self {
-        self.set_cookie(cookie);
+    /// Start building a request.
+    ///
+    /// An invalid `uri` doesn't panic; it's reported as [`Error::InvalidUri`], or
+    /// [`Error::InvalidUrl`] with a [`UrlParser`], once the request is awaited.
+    pub fn method(&self, method: Method, uri: impl IntoUri) -> RequestBuilder<B> {
+        match uri.into_uri(self.urls.as_ref()) {
+            Ok(uri) => RequestBuilder::new(Request::new(method, uri), self),
+            Err(error) => RequestBuilder::failed(Request::new(method, Uri::default()), error, self),
+        }
+    }
+
+    /// Check URLs given as strings against RFC 3986 with `parser`, e.g. to fix common mistakes.
+    ///
+    /// Without one, strings are converted as by [`Uri`] itself, which also accepts relative
+    /// URLs such as `/users` for use with [`Overrides::base`].
+    pub fn url_parser(mut self, parser: UrlParser) -> Self {
+        self.urls = Some(parser);
+        self
+    }
+
+    pub fn cookie(mut self, cookie: Cookie<'static>) -> Self {
+        self.cookies
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .add(cookie);
         self
     }
 
@@ -44,24 +134,104 @@ impl<B: ClientBackend> Client<B> {
         self.cookie_store = false;
     }
 
-    fn set_cookie(&self, cookie: Cookie<'static>) {
-        self.cookies.write().unwrap().add_original(cookie);
+    /// Fail requests that take longer than `timeout` to receive the response head with
+    /// [`Error::Timeout`].
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.config_mut().timeout = Some(timeout);
+        self
+    }
+
+    /// Send `user_agent` as the `User-Agent` of requests that don't set their own.
+    pub fn user_agent(mut self, user_agent: HeaderValue) -> Self {
+        self.config_mut().user_agent = Some(user_agent);
+        self
     }
 
-    pub async fn send(&self, request: Request) -> http_kit::Result<Response> {
+    fn config_mut(&mut self) -> &mut Config {
+        Arc::make_mut(
+            self.config
+                .get_mut()
+                .unwrap_or_else(PoisonError::into_inner),
+        )
+    }
+
+    /// The current settings.
+    pub fn config(&self) -> Arc<Config> {
+        self.config
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Change the settings of a client in use, keeping its connection pool.
+    ///
+    /// Only the settings in [`Config`], the timeout and the default `User-Agent`, can be
+    /// updated. Requests built but not yet awaited pick the update up; requests already sent
+    /// keep the settings they started with. Middleware such as [`limit::RateLimit`] is
+    /// reconfigured through its own handle instead, and log levels belong to the `tracing`
+    /// subscriber. The default backend has no proxy support to update.
+    pub fn update(&self, update: impl FnOnce(&mut Config)) {
+        let mut config = self.config.write().unwrap_or_else(PoisonError::into_inner);
+        update(Arc::make_mut(&mut config));
+    }
+
+    /// Decode bodies of `media_type` with `decoder` in [`ResponseExt::decode`].
+    ///
+    /// JSON is understood out of the box, including types such as `application/problem+json`.
+    pub fn decoder(mut self, media_type: &str, decoder: impl Decoder + 'static) -> Self {
+        self.decoders.insert(media_type, Arc::new(decoder));
+        self
+    }
+
+    /// Register an observer notified about every request this client sends.
+    pub fn observer(mut self, observer: impl ClientObserver + 'static) -> Self {
+        self.observers.push(Arc::new(observer));
+        self
+    }
+
+    /// Wrap every request this client sends in `middleware`.
+    ///
+    /// Middleware added first sees the request first and the response last.
+    pub fn middleware(mut self, middleware: impl Middleware) -> Self {
+        self.middlewares.push(Arc::new(middleware));
+        self
+    }
+
+    /// Insert `middleware` at `index` of the stack; an index past the end appends it.
+    pub fn insert_middleware(&mut self, index: usize, middleware: impl Middleware) {
+        self.middlewares.insert(index, Arc::new(middleware));
+    }
+
+    /// Replace the first layer named `name` with `middleware`, returning whether one was found.
+    pub fn replace_middleware(&mut self, name: &str, middleware: impl Middleware) -> bool {
+        self.middlewares.replace(name, Arc::new(middleware))
+    }
+
+    /// The names of the middleware stack, outermost first.
+    pub fn layers(&self) -> Vec<&str> {
+        self.middlewares.names()
+    }
+
+    /// How well header names and values are being reused across requests.
+    pub fn header_stats(&self) -> InternStats {
+        self.headers.stats()
+    }
+
+    pub async fn send(&self, request: Request) -> Result<Response> {
         RequestBuilder::new(request, self).await
     }
+
+    /// Send many requests with bounded parallelism over this client's connection pool.
+    pub fn send_all(&self, requests: impl IntoIterator<Item = Request>) -> Batch<'_, B> {
+        Batch::new(self, requests.into_iter().collect())
+    }
 }
 
 macro_rules! impl_client {
     ($(($name:ident,$method:tt)),*) => {
         impl <B:ClientBackend>Client<B>{
             $(
-                pub fn $name<U>(&self, uri: U) -> RequestBuilder<B>
-                where
-                    U: TryInto<Uri>,
-                    U::Error: Debug,
-                {
+                pub fn $name(&self, uri: impl IntoUri) -> RequestBuilder<B> {
                     self.method(Method::$method,uri)
                 }
             )*
@@ -69,11 +239,7 @@ macro_rules! impl_client {
 
         $(
             #[doc = concat!("Send a `",stringify!($method),"` request.")]
-            pub fn $name<U>(uri: U) -> RequestBuilder<'static, DefaultBackend>
-            where
-                U: TryInto<Uri>,
-                U::Error: Debug,
-            {
+            pub fn $name(uri: impl IntoUri) -> RequestBuilder<'static, DefaultBackend> {
                 DEFAULT_CLIENT.$name(uri)
             }
         )*
@@ -85,11 +251,268 @@ impl_client![(get, GET), (post, POST), (put, PUT), (delete, DELETE)];
 pub struct RequestBuilder<'a, B> {
     request: Request,
     client: &'a Client<B>,
+    abort: Option<AbortHandle>,
+    error: Option<Error>,
+    insecure_cookies: bool,
 }
 
 impl<'a, B: ClientBackend> RequestBuilder<'a, B> {
     fn new(request: Request, client: &'a Client<B>) -> Self {
-        Self { request, client }
+        Self {
+            request,
+            client,
+            abort: None,
+            error: None,
+            insecure_cookies: false,
+        }
+    }
+
+    fn failed(request: Request, error: Error, client: &'a Client<B>) -> Self {
+        let mut builder = Self::new(request, client);
+        builder.error = Some(error);
+        builder
+    }
+
+    /// Get a handle that cancels this request once it has been sent.
+    pub fn abort_handle(&mut self) -> AbortHandle {
+        self.abort.get_or_insert_with(AbortHandle::new).clone()
+    }
+
+    /// Stream the request body from the returned sender, which may keep writing after the
+    /// response has arrived.
+    ///
+    /// This replaces any body set before. The request is marked as [`Streaming`], so
+    /// middleware that would buffer the body passes it through instead.
+    pub fn body_sender(&mut self) -> BodySender {
+        let (sender, body) = BodySender::channel();
+        self.request.replace_body(body);
+        self.request.extensions_mut().insert(Streaming);
+        sender
+    }
+
+    /// Split the exchange into a sender for the request body and the response, for protocols
+    /// that keep writing while they read.
+    ///
+    /// Nothing is sent until the [`ResponseFuture`] is polled, so write from another task or
+    /// alongside it, e.g. with `tokio::join!`. Once the response has arrived, its body can be
+    /// read while the sender is still in use.
+    ///
+    /// The default backend speaks HTTP/1.1 only, where this relies on the server answering
+    /// before it has read the whole request body; many servers and proxies wait for it
+    /// instead. HTTP/2 isn't supported.
+    pub fn into_duplex(mut self) -> (BodySender, ResponseFuture<'a>) {
+        let sender = self.body_sender();
+        (sender, self.into_future())
+    }
+
+    /// Abort this request when `scope` is cancelled or dropped.
+    pub fn scope<T: Send + 'static>(mut self, scope: &RequestScope<T>) -> Self {
+        scope.track(self.abort_handle());
+        self
+    }
+
+    /// Exchange `Secure` cookies over plain HTTP for this request.
+    ///
+    /// **Testing only.** This lets local plaintext test servers exercise authenticated flows,
+    /// but leaks cookies that are meant to stay on HTTPS. Disabled by default.
+    pub fn danger_insecure_cookies(mut self) -> Self {
+        self.insecure_cookies = true;
+        self
+    }
+
+    /// Set a header, reusing the name and value parsed for earlier requests of this client.
+    ///
+    /// Values of credential headers such as `Authorization` aren't kept for reuse; they're
+    /// marked sensitive instead.
+    ///
+    /// An invalid name or value is reported as [`Error::InvalidHeader`] once the request is
+    /// awaited.
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        let header = self.client.headers.name(name).and_then(|name| {
+            let value = self.client.headers.value(&name, value)?;
+            Ok((name, value))
+        });
+        match header {
+            Ok((name, value)) => {
+                self.request.insert_header(name, value);
+            }
+            Err(error) => {
+                self.error.get_or_insert(Error::InvalidHeader(error));
+            }
+        }
+        self
+    }
+
+    /// Prefer `languages` in order for this request, overriding the preferences of
+    /// [`language::Language`].
+    ///
+    /// Invalid languages are reported as [`Error::InvalidLanguage`] once the request is awaited.
+    pub fn language<'l>(mut self, languages: impl IntoIterator<Item = &'l str>) -> Self {
+        match language::Language::new(languages) {
+            Ok(language) => {
+                self.request
+                    .insert_header(header::ACCEPT_LANGUAGE, language.header);
+            }
+            Err(error) => {
+                self.error.get_or_insert(error);
+            }
+        }
+        self
+    }
+
+    /// Ask for API `version` in this request, overriding the default of
+    /// [`versioning::Versioning`].
+    pub fn api_version(mut self, version: impl Into<Arc<str>>) -> Self {
+        self.request
+            .extensions_mut()
+            .insert(versioning::RequestedVersion(version.into()));
+        self
+    }
+
+    /// Send this request on behalf of the tenant `tag`, for middleware such as [`limit::Fair`].
+    pub fn tag(mut self, tag: impl Into<Arc<str>>) -> Self {
+        self.request.extensions_mut().insert(limit::Tag(tag.into()));
+        self
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "request",
+            skip_all,
+            fields(
+                method = %self.request.method(),
+                host = self.request.uri().host(),
+                status = tracing::field::Empty,
+                duration = tracing::field::Empty,
+            )
+        )
+    )]
+    async fn send(mut self) -> Result<Response> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        Overrides::apply(&mut self.request)?;
+
+        let info = Arc::new(RequestInfo::new(&self.request));
+        let uri = self.request.uri().clone();
+        self.client.observers.request(&self.request);
+        let start = Instant::now();
+
+        let client = self.client;
+        let config = client.config();
+        let abort = self.abort.take();
+        let dispatch = async {
+            match config.timeout {
+                Some(timeout) => {
+                    tokio::time::timeout(timeout, self.dispatch(&client.backend, &config))
+                        .await
+                        .unwrap_or_else(|_| Err(Error::Timeout))
+                }
+                None => self.dispatch(&client.backend, &config).await,
+            }
+        };
+        let mut result = match &abort {
+            Some(abort) => abort.run(dispatch).await,
+            None => dispatch.await,
+        };
+        if let Ok(response) = &mut result {
+            response.extensions_mut().insert(client.decoders.clone());
+            response.extensions_mut().insert(info.clone());
+            if let Some(abort) = abort {
+                let body = response.replace_body(Body::empty());
+                response.replace_body(abort.guard(body));
+            }
+        }
+
+        let elapsed = start.elapsed();
+        #[cfg(feature = "tracing")]
+        {
+            let span = tracing::Span::current();
+            span.record("duration", tracing::field::debug(elapsed));
+            match &result {
+                Ok(response) => {
+                    span.record("status", response.status().as_u16());
+                }
+                Err(error) => tracing::debug!(%error, "request failed"),
+            }
+        }
+        self.client
+            .observers
+            .finish(&info.method, &uri, &result, elapsed);
+        result
+    }
+
+    /// Build the request exactly as it would be sent, and return it instead of sending it.
+    ///
+    /// Scoped [`Overrides`], cookies and the client's middleware are all applied. The request
+    /// carries [`DryRun`] in its extensions, which the mirroring and audit layers skip; other
+    /// middleware, such as rate limiting, runs as usual. The body is buffered so it can be
+    /// inspected.
+    pub async fn dry_run(mut self) -> Result<Request> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        Overrides::apply(&mut self.request)?;
+        self.request.extensions_mut().insert(DryRun);
+
+        let capture = Capture::default();
+        let config = self.client.config();
+        self.dispatch(&capture, &config).await?;
+        // Middleware may answer without reaching the end of the stack.
+        let mut request = capture
+            .into_request()
+            .unwrap_or_else(|| replace(&mut self.request, Request::new(Method::GET, "/")));
+
+        let body = request.into_bytes().await?;
+        request.replace_body(body);
+        Ok(request)
+    }
+
+    async fn dispatch(
+        &mut self,
+        endpoint: &(dyn Endpoint + Sync),
+        config: &Config,
+    ) -> Result<Response> {
+        let uri = self.request.uri().clone();
+        if let Some(user_agent) = &config.user_agent {
+            if !self.request.headers().contains_key(header::USER_AGENT) {
+                self.request
+                    .insert_header(header::USER_AGENT, user_agent.clone());
+            }
+        }
+        if self.client.cookie_store {
+            let cookies = self
+                .client
+                .cookies
+                .read()
+                .unwrap_or_else(PoisonError::into_inner)
+                .header(&uri, self.insecure_cookies);
+            if let Some(cookies) = cookies {
+                let value = HeaderValue::try_from(cookies)
+                    .map_err(|error| Error::InvalidHeader(error.into()))?;
+                self.request.insert_header(header::COOKIE, value);
+            }
+        }
+
+        let response = Next::new(self.client.middlewares.as_slice(), endpoint)
+            .run(&mut self.request)
+            .await?;
+        if self.client.cookie_store {
+            let mut cookies = self
+                .client
+                .cookies
+                .write()
+                .unwrap_or_else(PoisonError::into_inner);
+
+            for cookie in response.headers().get_all(header::SET_COOKIE) {
+                // Like unparsable cookies, those that aren't UTF-8 are ignored.
+                if let Ok(cookie) = std::str::from_utf8(cookie.as_bytes()) {
+                    cookies.store(&uri, cookie, self.insecure_cookies);
+                }
+            }
+        }
+        Ok(response)
     }
 }
 
@@ -106,53 +529,28 @@ impl<'a, B> DerefMut for RequestBuilder<'a, B> {
     }
 }
 
+/// The response to a [`RequestBuilder`], which is `Send` so requests can be awaited in spawned
+/// tasks such as those of a [`RequestScope`].
 pub struct ResponseFuture<'a> {
-    future: Pin<Box<dyn 'a + Future<Output = http_kit::Result<Response>>>>,
+    future: Pin<Box<dyn 'a + Future<Output = Result<Response>> + Send>>,
 }
 
 impl<'a> Future for ResponseFuture<'a> {
-    type Output = http_kit::Result<Response>;
+    type Output = Result<Response>;
 
-    fn poll(
-        mut self: Pin<&mut Self>,
-        cx: &mut std::task::Context<'_>,
-    ) -> std::task::Poll<Self::Output> {
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         self.future.as_mut().poll(cx)
     }
 }
 
 impl<'a, B: ClientBackend> IntoFuture for RequestBuilder<'a, B> {
-    type Output = http_kit::Result<Response>;
+    type Output = Result<Response>;
 
     type IntoFuture = ResponseFuture<'a>;
 
-    fn into_future(mut self) -> Self::IntoFuture {
+    fn into_future(self) -> Self::IntoFuture {
         ResponseFuture {
-            future: Box::pin(async move {
-                if self.client.cookie_store {
-                    let cookies = self.client.cookies.read().unwrap();
-                    let vec: Vec<String> =
-                        cookies.iter().map(|v| v.encoded().to_string()).collect();
-                    self.request.insert_header(
-                        header::COOKIE,
-                        HeaderValue::try_from(vec.join(";")).unwrap(),
-                    );
-                }
-
-                let mut result = self.client.backend.call_endpoint(&mut self.request).await;
-                if self.client.cookie_store {
-                    result = result.map(|response| {
-                        let mut cookies = self.client.cookies.write().unwrap();
-
-                        for cookie in response.headers().get_all(header::SET_COOKIE) {
-                            let cookie = String::from_utf8(cookie.as_bytes().to_vec()).unwrap();
-                            cookies.add_original(Cookie::parse(cookie).unwrap());
-                        }
-                        response
-                    });
-                }
-                result
-            }),
+            future: Box::pin(self.send()),
         }
     }
 }
@@ -161,13 +559,173 @@ impl Client<DefaultBackend> {
     pub fn new() -> Self {
         Self::default()
     }
+
+    /// Create a client configured by environment variables, for deployments configured
+    /// through their environment:
+    ///
+    /// - `ZENWAVE_TIMEOUT`: seconds to wait for a response head, see [`Client::timeout`].
+    /// - `ZENWAVE_CONNECT_TIMEOUT`: seconds to wait for a connection to be established.
+    /// - `ZENWAVE_USER_AGENT`: the default `User-Agent`, see [`Client::user_agent`].
+    ///
+    /// Durations may be fractional, e.g. `0.5`. Unset or empty variables keep the defaults;
+    /// a malformed one is reported as [`Error::InvalidEnv`].
+    ///
+    /// The default backend has no proxy or TLS support, so the curl conventions for those
+    /// (`HTTPS_PROXY`, `NO_PROXY`, `SSL_CERT_FILE`, ...) aren't read.
+    pub fn from_env() -> Result<Self> {
+        let mut client = match env_duration("ZENWAVE_CONNECT_TIMEOUT")? {
+            Some(timeout) => {
+                let mut connector = hyper::client::HttpConnector::new();
+                connector.set_connect_timeout(Some(timeout));
+                Self::with_backend(HyperBackend::with_connector(connector))
+            }
+            None => Self::new(),
+        };
+        if let Some(timeout) = env_duration("ZENWAVE_TIMEOUT")? {
+            client = client.timeout(timeout);
+        }
+        if let Some(user_agent) = env_var("ZENWAVE_USER_AGENT") {
+            let user_agent = HeaderValue::try_from(user_agent)
+                .map_err(|_| Error::InvalidEnv("ZENWAVE_USER_AGENT"))?;
+            client = client.user_agent(user_agent);
+        }
+        Ok(client)
+    }
+}
+
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name)
+        .ok()
+        .filter(|value| !value.trim().is_empty())
+}
+
+fn env_duration(name: &'static str) -> Result<Option<Duration>> {
+    env_var(name)
+        .map(|value| {
+            value
+                .trim()
+                .parse()
+                .ok()
+                .and_then(|seconds| Duration::try_from_secs_f64(seconds).ok())
+                .ok_or(Error::InvalidEnv(name))
+        })
+        .transpose()
 }
 
 static DEFAULT_CLIENT: Lazy<Client> = Lazy::new(|| Client::default());
 
 #[cfg(test)]
 mod test {
-    use crate::Client;
+    use crate::{Client, ClientBackend, Error};
+    use async_trait::async_trait;
+    use http_kit::{header, Body, Endpoint, Request, Response};
+    use hyper::http::{self, HeaderValue};
+    use std::sync::{Arc, Mutex, PoisonError};
+    use std::time::Duration;
+
+    const VARIABLES: [&str; 3] = [
+        "ZENWAVE_TIMEOUT",
+        "ZENWAVE_CONNECT_TIMEOUT",
+        "ZENWAVE_USER_AGENT",
+    ];
+
+    /// The environment is shared by every test thread, so tests setting it take turns.
+    static ENV: Mutex<()> = Mutex::new(());
+
+    /// Run `Client::from_env` with exactly `variables` set.
+    fn from_env(variables: &[(&str, &str)]) -> crate::Result<Client> {
+        let _guard = ENV.lock().unwrap_or_else(PoisonError::into_inner);
+        for name in VARIABLES {
+            std::env::remove_var(name);
+        }
+        for (name, value) in variables {
+            std::env::set_var(name, value);
+        }
+        let client = Client::from_env();
+        for name in VARIABLES {
+            std::env::remove_var(name);
+        }
+        client
+    }
+
+    /// Answers with the `User-Agent` it was sent, after sleeping for the milliseconds given
+    /// in the query.
+    struct Agent;
+
+    #[async_trait]
+    impl Endpoint for Agent {
+        async fn call_endpoint(&self, request: &mut Request) -> http_kit::Result<Response> {
+            if let Some(millis) = request.uri().query() {
+                tokio::time::sleep(Duration::from_millis(millis.parse().unwrap())).await;
+            }
+            let mut response = http::Response::builder();
+            if let Some(user_agent) = request.headers().get(header::USER_AGENT) {
+                response = response.header(header::USER_AGENT, user_agent);
+            }
+            Ok(response.body(Body::empty()).unwrap().into())
+        }
+    }
+
+    impl ClientBackend for Agent {}
+
+    #[tokio::test]
+    async fn updates_reach_built_requests() {
+        let client = Client::with_backend(Agent).user_agent(HeaderValue::from_static("old"));
+        let request = client.get("http://example.com/");
+        client.update(|config| config.user_agent = Some(HeaderValue::from_static("new")));
+        let response = request.await.unwrap();
+        assert_eq!(response.headers()[header::USER_AGENT], "new");
+    }
+
+    #[tokio::test]
+    async fn updates_reach_shared_clients() {
+        let client = Arc::new(Client::with_backend(Agent));
+        let shared = client.clone();
+        assert!(shared.get("http://example.com/?50").await.is_ok());
+
+        client.update(|config| config.timeout = Some(Duration::from_millis(10)));
+        assert_eq!(shared.config().timeout, Some(Duration::from_millis(10)));
+        let result = shared.get("http://example.com/?1000").await;
+        assert!(matches!(result, Err(Error::Timeout)));
+    }
+
+    #[test]
+    fn from_env_defaults() {
+        let client = from_env(&[("ZENWAVE_TIMEOUT", ""), ("ZENWAVE_USER_AGENT", " ")]).unwrap();
+        let config = client.config();
+        assert_eq!(config.timeout, None);
+        assert_eq!(config.user_agent, None);
+    }
+
+    #[test]
+    fn from_env_valid() {
+        let client = from_env(&[
+            ("ZENWAVE_TIMEOUT", " 0.5 "),
+            ("ZENWAVE_CONNECT_TIMEOUT", "2"),
+            ("ZENWAVE_USER_AGENT", "zenwave-test/1.0"),
+        ])
+        .unwrap();
+        let config = client.config();
+        assert_eq!(config.timeout, Some(Duration::from_millis(500)));
+        assert_eq!(config.user_agent.as_ref().unwrap(), "zenwave-test/1.0");
+    }
+
+    #[test]
+    fn from_env_invalid() {
+        for (name, value) in [
+            ("ZENWAVE_TIMEOUT", "abc"),
+            ("ZENWAVE_TIMEOUT", "-1"),
+            ("ZENWAVE_CONNECT_TIMEOUT", "5s"),
+            ("ZENWAVE_CONNECT_TIMEOUT", "inf"),
+            ("ZENWAVE_USER_AGENT", "bad\nvalue"),
+        ] {
+            let result = from_env(&[(name, value)]);
+            assert!(
+                matches!(result, Err(Error::InvalidEnv(invalid)) if invalid == name),
+                "{name}={value:?} was accepted"
+            );
+        }
+    }
 
     #[tokio::test]
     async fn example() {
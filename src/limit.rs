@@ -0,0 +1,533 @@
+//! Client-side throttling.
+
+use crate::{Middleware, Next, Result};
+use async_trait::async_trait;
+use http_kit::{Request, Response};
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, OwnedSemaphorePermit, Semaphore};
+
+/// The tenant a request is sent on behalf of, set with
+/// [`RequestBuilder::tag`](crate::RequestBuilder::tag).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Tag(pub Arc<str>);
+
+/// Middleware limiting the request rate and the number of concurrent requests per host.
+///
+/// Requests over a limit wait inside the [`ResponseFuture`](crate::ResponseFuture) until there
+/// is capacity rather than failing. A concurrency slot is released once the response head has
+/// been received. Only hosts with requests in flight or waiting are kept track of.
+#[derive(Debug, Default)]
+pub struct RateLimit {
+    bucket: Mutex<Option<Bucket>>,
+    per_host: Option<usize>,
+    hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    rate: f64,
+    burst: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(requests: f64) -> Self {
+        let rate = requests.max(f64::EPSILON);
+        let burst = rate.max(1.0);
+        Self {
+            rate,
+            burst,
+            tokens: burst,
+            updated: Instant::now(),
+        }
+    }
+
+    /// Take a token, returning how long the caller has to wait for it to become available.
+    fn reserve(&mut self) -> Option<Duration> {
+        let now = Instant::now();
+        let refill = now.duration_since(self.updated).as_secs_f64() * self.rate;
+        self.tokens = (self.tokens + refill).min(self.burst) - 1.0;
+        self.updated = now;
+        (self.tokens < 0.0).then(|| Duration::from_secs_f64(-self.tokens / self.rate))
+    }
+}
+
+impl RateLimit {
+    /// Create a limiter that doesn't limit anything yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow at most `requests` per second on average, with bursts of the same size.
+    pub fn per_second(mut self, requests: f64) -> Self {
+        *self
+            .bucket
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner) = Some(Bucket::new(requests));
+        self
+    }
+
+    /// Allow bursts of up to `burst` requests. Has no effect unless a rate is set.
+    pub fn burst(mut self, burst: u32) -> Self {
+        let bucket = self
+            .bucket
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        if let Some(bucket) = bucket {
+            bucket.burst = f64::from(burst.max(1));
+            bucket.tokens = bucket.burst;
+        }
+        self
+    }
+
+    /// Change the request rate of a limiter in use, or lift it with `None`.
+    ///
+    /// Share the limiter through an `Arc` to keep a handle on it after adding it to a client.
+    /// Bursts become as large as the new rate.
+    pub fn set_per_second(&self, requests: Option<f64>) {
+        *self.bucket.lock().unwrap_or_else(PoisonError::into_inner) = requests.map(Bucket::new);
+    }
+
+    /// Allow at most `requests` in flight to the same host.
+    pub fn per_host(mut self, requests: usize) -> Self {
+        self.per_host = Some(requests);
+        self
+    }
+
+    fn host(&self, host: &str, permits: usize) -> Arc<Semaphore> {
+        let mut hosts = self.hosts();
+        if let Some(semaphore) = hosts.get(host) {
+            return semaphore.clone();
+        }
+        // Permits and waiters hold the semaphore as well, so hosts nothing is sent to anymore
+        // are dropped before another one is added.
+        hosts.retain(|_, semaphore| Arc::strong_count(semaphore) > 1);
+        let semaphore = Arc::new(Semaphore::new(permits));
+        hosts.insert(host.to_owned(), semaphore.clone());
+        semaphore
+    }
+
+    fn hosts(&self) -> MutexGuard<'_, HashMap<String, Arc<Semaphore>>> {
+        self.hosts.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Wait until the limits allow another request to `host`. The concurrency slot, if any, is
+    /// held until the returned permit is dropped.
+    pub(crate) async fn acquire(&self, host: &str) -> Option<OwnedSemaphorePermit> {
+        let permit = match self.per_host {
+            // Host semaphores are never closed, so acquiring can't fail.
+            Some(permits) => self.host(host, permits).acquire_owned().await.ok(),
+            None => None,
+        };
+        if let Some(delay) = self.reserve() {
+            tokio::time::sleep(delay).await;
+        }
+        permit
+    }
+
+    fn reserve(&self) -> Option<Duration> {
+        self.bucket
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .as_mut()?
+            .reserve()
+    }
+}
+
+#[async_trait]
+impl Middleware for RateLimit {
+    async fn handle(&self, request: &mut Request, next: Next<'_>) -> Result<Response> {
+        // A batch sharing this limiter with the client already waited for it.
+        if request.extensions().get::<Acquired>() == Some(&Acquired::by(self)) {
+            return next.run(request).await;
+        }
+        let _permit = self.acquire(request.uri().host().unwrap_or_default()).await;
+        next.run(request).await
+    }
+}
+
+/// Marks requests that already passed the [`RateLimit`] at this address, in their extensions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Acquired(usize);
+
+impl Acquired {
+    pub fn by(limit: &RateLimit) -> Self {
+        Self(limit as *const RateLimit as usize)
+    }
+}
+
+/// Middleware sharing a client's concurrency between tenants in proportion to their weights.
+///
+/// Requests are grouped by their [`Tag`], untagged requests forming a group of their own. While
+/// every slot is taken, a freed slot goes to the waiting group with the fewest requests in
+/// flight relative to its weight, so a noisy tenant can't starve the others. Slots nobody else
+/// waits for are never left idle. A group may also be given its own request rate.
+#[derive(Debug)]
+pub struct Fair {
+    shares: HashMap<Arc<str>, Share>,
+    state: Arc<Mutex<State>>,
+}
+
+#[derive(Debug)]
+struct Share {
+    weight: u32,
+    bucket: Option<Mutex<Bucket>>,
+}
+
+#[derive(Debug)]
+struct State {
+    concurrency: usize,
+    in_flight: usize,
+    groups: HashMap<Arc<str>, Group>,
+}
+
+#[derive(Debug, Default)]
+struct Group {
+    weight: u32,
+    in_flight: usize,
+    waiting: VecDeque<oneshot::Sender<Slot>>,
+}
+
+/// A concurrency slot of [`Fair`], given back when dropped.
+#[derive(Debug)]
+struct Slot {
+    state: Option<Arc<Mutex<State>>>,
+    tag: Arc<str>,
+}
+
+impl Fair {
+    /// Allow at most `concurrency` requests in flight, shared equally until weights are set.
+    pub fn new(concurrency: usize) -> Self {
+        Self {
+            shares: HashMap::new(),
+            state: Arc::new(Mutex::new(State {
+                concurrency: concurrency.max(1),
+                in_flight: 0,
+                groups: HashMap::new(),
+            })),
+        }
+    }
+
+    /// Give requests tagged `tag` a share of `weight`; every tag has a weight of 1 otherwise.
+    pub fn weight(mut self, tag: impl Into<Arc<str>>, weight: u32) -> Self {
+        self.share(tag.into()).weight = weight.max(1);
+        self
+    }
+
+    /// Allow requests tagged `tag` at most `requests` per second on average.
+    pub fn rate(mut self, tag: impl Into<Arc<str>>, requests: f64) -> Self {
+        self.share(tag.into()).bucket = Some(Mutex::new(Bucket::new(requests)));
+        self
+    }
+
+    fn share(&mut self, tag: Arc<str>) -> &mut Share {
+        self.shares.entry(tag).or_insert(Share {
+            weight: 1,
+            bucket: None,
+        })
+    }
+
+    async fn acquire(&self, tag: Arc<str>) -> Slot {
+        let receiver = {
+            let mut state = lock(&self.state);
+            let queued = state.groups.values().any(|group| !group.waiting.is_empty());
+            if !queued && state.in_flight < state.concurrency {
+                state.start(&tag);
+                return Slot {
+                    state: Some(self.state.clone()),
+                    tag,
+                };
+            }
+            let weight = self.shares.get(&tag).map_or(1, |share| share.weight);
+            let (sender, receiver) = oneshot::channel();
+            let group = state.groups.entry(tag).or_default();
+            group.weight = weight;
+            group.waiting.push_back(sender);
+            receiver
+        };
+        // The sender is only dropped with the limiter, which outlives the requests it handles.
+        receiver
+            .await
+            .expect("`Fair` was dropped while a request waited")
+    }
+
+    fn reserve(&self, tag: &str) -> Option<Duration> {
+        self.shares
+            .get(tag)?
+            .bucket
+            .as_ref()?
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .reserve()
+    }
+}
+
+impl State {
+    fn start(&mut self, tag: &Arc<str>) {
+        self.in_flight += 1;
+        self.groups.entry(tag.clone()).or_default().in_flight += 1;
+    }
+
+    fn finish(&mut self, tag: &str, state: &Arc<Mutex<State>>) {
+        self.in_flight -= 1;
+        if let Some(group) = self.groups.get_mut(tag) {
+            group.in_flight -= 1;
+        }
+        while self.in_flight < self.concurrency {
+            let Some(tag) = self.next() else { break };
+            let group = self.groups.get_mut(&tag).expect("picked from the map");
+            let sender = group.waiting.pop_front().expect("picked for waiting");
+            self.start(&tag);
+            let slot = Slot {
+                state: Some(state.clone()),
+                tag,
+            };
+            // The request stopped waiting, so give its slot to the next one.
+            if let Err(mut slot) = sender.send(slot) {
+                slot.state = None;
+                self.in_flight -= 1;
+                if let Some(group) = self.groups.get_mut(&slot.tag) {
+                    group.in_flight -= 1;
+                }
+            }
+        }
+        self.groups
+            .retain(|_, group| group.in_flight > 0 || !group.waiting.is_empty());
+    }
+
+    /// The waiting group with the fewest requests in flight for its weight.
+    fn next(&self) -> Option<Arc<str>> {
+        self.groups
+            .iter()
+            .filter(|(_, group)| !group.waiting.is_empty())
+            .min_by_key(|(_, group)| Load(group.in_flight, group.weight.max(1)))
+            .map(|(tag, _)| tag.clone())
+    }
+}
+
+/// Requests in flight per unit of weight, compared without rounding.
+struct Load(usize, u32);
+
+impl PartialEq for Load {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other).is_eq()
+    }
+}
+
+impl Eq for Load {}
+
+impl Ord for Load {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        let this = self.0 as u64 * u64::from(other.1);
+        let other = other.0 as u64 * u64::from(self.1);
+        this.cmp(&other)
+    }
+}
+
+impl PartialOrd for Load {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        if let Some(state) = self.state.take() {
+            lock(&state).finish(&self.tag, &state);
+        }
+    }
+}
+
+fn lock(state: &Mutex<State>) -> MutexGuard<'_, State> {
+    state.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+#[async_trait]
+impl Middleware for Fair {
+    async fn handle(&self, request: &mut Request, next: Next<'_>) -> Result<Response> {
+        let tag = match request.extensions().get::<Tag>() {
+            Some(tag) => tag.0.clone(),
+            None => Arc::from(""),
+        };
+        if let Some(delay) = self.reserve(&tag) {
+            tokio::time::sleep(delay).await;
+        }
+        let _slot = self.acquire(tag).await;
+        next.run(request).await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{lock, Fair, RateLimit, Slot};
+    use crate::middleware::Capture;
+    use crate::{Middleware, Next};
+    use futures_util::FutureExt;
+    use http_kit::{Method, Request};
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::time::Duration;
+
+    type Waiter<'a> = Pin<Box<dyn Future<Output = Slot> + 'a>>;
+
+    /// Queue up a request tagged `tag`, which must not get a slot straight away.
+    fn wait<'a>(fair: &'a Fair, tag: &str) -> Waiter<'a> {
+        let mut waiter: Waiter<'a> = Box::pin(fair.acquire(tag.into()));
+        assert!(
+            (&mut waiter).now_or_never().is_none(),
+            "{tag} wasn't queued"
+        );
+        waiter
+    }
+
+    fn ready(waiters: &mut Vec<Waiter<'_>>) -> Vec<Slot> {
+        let mut slots = Vec::new();
+        waiters.retain_mut(|waiter| match waiter.now_or_never() {
+            Some(slot) => {
+                slots.push(slot);
+                false
+            }
+            None => true,
+        });
+        slots
+    }
+
+    fn hold(fair: &Fair, tag: &str, count: usize) -> Vec<Slot> {
+        (0..count)
+            .map(|_| {
+                fair.acquire(tag.into())
+                    .now_or_never()
+                    .expect("a free slot")
+            })
+            .collect()
+    }
+
+    #[test]
+    fn slots_follow_weights() {
+        let fair = Fair::new(4).weight("a", 3);
+        let mut busy = hold(&fair, "busy", 4);
+        let mut a: Vec<_> = (0..8).map(|_| wait(&fair, "a")).collect();
+        let mut b: Vec<_> = (0..8).map(|_| wait(&fair, "b")).collect();
+
+        busy.clear();
+        let mut running_a = ready(&mut a);
+        let running_b = ready(&mut b);
+        assert_eq!((running_a.len(), running_b.len()), (3, 1));
+
+        // `a` has fewer requests in flight for its weight, so it gets the freed slot back.
+        running_a.pop();
+        assert_eq!((ready(&mut a).len(), ready(&mut b).len()), (1, 0));
+        assert_eq!(lock(&fair.state).in_flight, 4);
+    }
+
+    #[test]
+    fn cancelled_waiters_pass_their_slot_on() {
+        let fair = Fair::new(1);
+        let held = hold(&fair, "a", 1);
+        let before = wait(&fair, "b");
+        let after = wait(&fair, "b");
+        let mut last = vec![wait(&fair, "b")];
+
+        // Cancelled before the slot is freed: the hand-off fails and the next one is picked.
+        drop(before);
+        drop(held);
+        // Cancelled after the slot was handed to it, without ever being polled again.
+        drop(after);
+        let slots = ready(&mut last);
+        assert_eq!(slots.len(), 1);
+        assert_eq!(lock(&fair.state).in_flight, 1);
+
+        drop(slots);
+        let state = lock(&fair.state);
+        assert_eq!(state.in_flight, 0);
+        assert!(state.groups.is_empty());
+    }
+
+    #[test]
+    fn untagged_requests_are_a_group() {
+        let fair = Fair::new(2).weight("a", 1);
+        let mut held = hold(&fair, "a", 2);
+        let mut tagged = vec![wait(&fair, "a")];
+
+        let endpoint = Capture::default();
+        let mut request = Request::new(Method::GET, "http://example.com/");
+        let mut untagged = fair.handle(&mut request, Next::new(&[], &endpoint));
+        assert!((&mut untagged).now_or_never().is_none());
+
+        // The untagged group has nothing in flight, unlike `a`.
+        held.pop();
+        assert!(ready(&mut tagged).is_empty());
+        assert!(untagged.now_or_never().unwrap().is_ok());
+        assert!(endpoint.into_request().is_some());
+
+        // Its slot is given back once the response has arrived.
+        assert_eq!(ready(&mut tagged).len(), 1);
+    }
+
+    /// Pretend `elapsed` has passed since the bucket was last used.
+    fn rewind(limit: &RateLimit, elapsed: Duration) {
+        let mut bucket = limit.bucket.lock().unwrap();
+        let bucket = bucket.as_mut().unwrap();
+        bucket.updated -= elapsed;
+    }
+
+    #[test]
+    fn bursts() {
+        let limit = RateLimit::new().per_second(2.0);
+        assert_eq!(limit.reserve(), None);
+        assert_eq!(limit.reserve(), None);
+        let delay = limit.reserve().expect("the burst is spent");
+        assert!(delay > Duration::from_millis(400) && delay <= Duration::from_millis(500));
+
+        let limit = RateLimit::new().per_second(1.0).burst(5);
+        assert!((0..5).all(|_| limit.reserve().is_none()));
+        assert!(limit.reserve().is_some());
+    }
+
+    #[test]
+    fn refill() {
+        let limit = RateLimit::new().per_second(10.0).burst(2);
+        assert!((0..2).all(|_| limit.reserve().is_none()));
+
+        rewind(&limit, Duration::from_millis(100));
+        assert_eq!(limit.reserve(), None, "one token refilled");
+        assert!(limit.reserve().is_some());
+
+        // Tokens don't pile up past the burst, however long the limiter was idle.
+        rewind(&limit, Duration::from_secs(60));
+        assert!((0..2).all(|_| limit.reserve().is_none()));
+        assert!(limit.reserve().is_some());
+    }
+
+    #[test]
+    fn per_host() {
+        let limit = RateLimit::new().per_host(1);
+        let first = limit.acquire("a").now_or_never().unwrap();
+        assert!(first.is_some());
+
+        let mut second = Box::pin(limit.acquire("a"));
+        assert!((&mut second).now_or_never().is_none(), "over the cap");
+        let other = limit.acquire("b").now_or_never().unwrap();
+        assert!(other.is_some(), "hosts have their own cap");
+
+        drop(first);
+        assert!(second.now_or_never().unwrap().is_some());
+    }
+
+    #[test]
+    fn idle_hosts_are_dropped() {
+        let limit = RateLimit::new().per_host(2);
+        let held = limit.acquire("busy").now_or_never().unwrap();
+        for index in 0..100 {
+            let host = format!("{index}.example.com");
+            limit.acquire(&host).now_or_never().unwrap();
+        }
+        let hosts = limit.hosts();
+        assert_eq!(hosts.len(), 2);
+        assert!(hosts.contains_key("busy") && hosts.contains_key("99.example.com"));
+        drop(held);
+    }
+}
@@ -0,0 +1,207 @@
+//! Request duration histograms laid out like OpenTelemetry's exponential histograms.
+//!
+//! Register [`RequestMetrics`] as a [`ClientObserver`] and export its
+//! [`snapshot`](RequestMetrics::snapshot) as `http.client.request.duration`: every field of
+//! [`ExponentialHistogram`] maps one to one onto OTLP's `ExponentialHistogramDataPoint`.
+//!
+//! Bytes on the wire are counted per origin by wrapping the connector of the backend in
+//! [`Metered`](crate::backend::Metered).
+
+use crate::{ClientObserver, Error};
+use http_kit::{Method, Response, Uri};
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+/// The finest resolution OpenTelemetry allows; histograms start here and scale down as needed.
+const MAX_SCALE: i32 = 20;
+/// OpenTelemetry's default bucket limit.
+const MAX_BUCKETS: usize = 160;
+
+/// What a duration is recorded under.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Attributes {
+    /// `server.address`
+    pub host: String,
+    /// `http.request.method`
+    pub method: Method,
+    /// `http.response.status_code`, or `None` if the request failed.
+    pub status: Option<u16>,
+}
+
+/// A base-2 exponential histogram of durations in seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExponentialHistogram {
+    pub scale: i32,
+    pub count: u64,
+    pub sum: f64,
+    pub min: f64,
+    pub max: f64,
+    pub zero_count: u64,
+    pub positive: Buckets,
+}
+
+/// Bucket counts where `bucket_counts[i]` counts the values in
+/// `(base^(offset + i), base^(offset + i + 1)]`, with `base = 2^(2^-scale)`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Buckets {
+    pub offset: i32,
+    pub bucket_counts: Vec<u64>,
+}
+
+impl Default for ExponentialHistogram {
+    fn default() -> Self {
+        Self {
+            scale: MAX_SCALE,
+            count: 0,
+            sum: 0.0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+            zero_count: 0,
+            positive: Buckets::default(),
+        }
+    }
+}
+
+impl ExponentialHistogram {
+    pub fn record(&mut self, value: f64) {
+        self.count += 1;
+        self.sum += value;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+
+        if value <= 0.0 {
+            self.zero_count += 1;
+            return;
+        }
+        loop {
+            let index = self.index(value);
+            if self.positive.fits(index) {
+                self.positive.add(index, 1);
+                return;
+            }
+            self.downscale();
+        }
+    }
+
+    fn index(&self, value: f64) -> i32 {
+        (value.log2() * 2f64.powi(self.scale)).ceil() as i32 - 1
+    }
+
+    fn downscale(&mut self) {
+        let buckets = std::mem::take(&mut self.positive);
+        for (index, count) in (buckets.offset..).zip(buckets.bucket_counts) {
+            if count > 0 {
+                self.positive.add(index >> 1, count);
+            }
+        }
+        self.scale -= 1;
+    }
+}
+
+impl Buckets {
+    fn fits(&self, index: i32) -> bool {
+        if self.bucket_counts.is_empty() {
+            return true;
+        }
+        let high = self.offset + self.bucket_counts.len() as i32 - 1;
+        ((high.max(index) - self.offset.min(index)) as usize) < MAX_BUCKETS
+    }
+
+    fn add(&mut self, index: i32, count: u64) {
+        if self.bucket_counts.is_empty() {
+            self.offset = index;
+        } else if index < self.offset {
+            let missing = (self.offset - index) as usize;
+            self.bucket_counts
+                .splice(0..0, std::iter::repeat_n(0, missing));
+            self.offset = index;
+        }
+        let position = (index - self.offset) as usize;
+        if position >= self.bucket_counts.len() {
+            self.bucket_counts.resize(position + 1, 0);
+        }
+        self.bucket_counts[position] += count;
+    }
+}
+
+/// A [`ClientObserver`] recording request durations by host, method and status.
+#[derive(Debug, Default)]
+pub struct RequestMetrics {
+    histograms: Mutex<HashMap<Attributes, ExponentialHistogram>>,
+}
+
+impl RequestMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The histograms recorded so far. Values are cumulative, as OTLP's cumulative temporality
+    /// expects.
+    pub fn snapshot(&self) -> Vec<(Attributes, ExponentialHistogram)> {
+        self.histograms()
+            .iter()
+            .map(|(attributes, histogram)| (attributes.clone(), histogram.clone()))
+            .collect()
+    }
+
+    fn histograms(&self) -> MutexGuard<'_, HashMap<Attributes, ExponentialHistogram>> {
+        self.histograms
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn record(&self, method: &Method, uri: &Uri, status: Option<u16>, elapsed: Duration) {
+        let attributes = Attributes {
+            host: uri.host().unwrap_or_default().to_owned(),
+            method: method.clone(),
+            status,
+        };
+        self.histograms()
+            .entry(attributes)
+            .or_default()
+            .record(elapsed.as_secs_f64());
+    }
+}
+
+impl ClientObserver for RequestMetrics {
+    fn on_response(&self, method: &Method, uri: &Uri, response: &Response, elapsed: Duration) {
+        self.record(method, uri, Some(response.status().as_u16()), elapsed);
+    }
+
+    fn on_error(&self, method: &Method, uri: &Uri, _error: &Error, elapsed: Duration) {
+        self.record(method, uri, None, elapsed);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ExponentialHistogram, MAX_BUCKETS};
+
+    #[test]
+    fn buckets() {
+        let mut histogram = ExponentialHistogram {
+            scale: 0,
+            ..Default::default()
+        };
+        for value in [0.0, 1.0, 2.0, 3.0, 4.0, 5.0] {
+            histogram.record(value);
+        }
+        // With scale 0 bucket `i` holds `(2^i, 2^(i + 1)]`.
+        assert_eq!(histogram.zero_count, 1);
+        assert_eq!(histogram.positive.offset, -1);
+        assert_eq!(histogram.positive.bucket_counts, [1, 1, 2, 1]);
+        assert_eq!(histogram.sum, 15.0);
+    }
+
+    #[test]
+    fn downscales_to_fit() {
+        let mut histogram = ExponentialHistogram::default();
+        for value in [0.001, 0.01, 0.1, 1.0, 10.0, 100.0] {
+            histogram.record(value);
+        }
+        assert!(histogram.positive.bucket_counts.len() <= MAX_BUCKETS);
+        assert_eq!(histogram.positive.bucket_counts.iter().sum::<u64>(), 6);
+        assert!(histogram.scale < 20);
+    }
+}
@@ -0,0 +1,220 @@
+use crate::Result;
+use async_trait::async_trait;
+use http_kit::{Body, Endpoint, Method, Request, Response};
+use hyper::http;
+use std::fmt::{self, Debug};
+use std::mem::replace;
+use std::sync::{Arc, Mutex, PoisonError};
+
+/// A layer wrapped around every request a [`Client`](crate::Client) sends.
+///
+/// Middleware runs in the order it was added to the client. Each one decides whether and how
+/// to call the rest of the stack through [`Next::run`].
+#[async_trait]
+pub trait Middleware: Send + Sync + 'static {
+    async fn handle(&self, request: &mut Request, next: Next<'_>) -> Result<Response>;
+
+    /// The name this layer is listed under by [`Client::layers`](crate::Client::layers).
+    ///
+    /// Defaults to the type name without its module path or generics, e.g. `RateLimit`.
+    fn name(&self) -> &str {
+        let name = std::any::type_name::<Self>();
+        let name = name.split('<').next().unwrap_or(name);
+        name.rsplit("::").next().unwrap_or(name)
+    }
+}
+
+/// Lets a layer be shared, e.g. one [`RateLimit`](crate::limit::RateLimit) between clients.
+#[async_trait]
+impl<M: Middleware> Middleware for Arc<M> {
+    async fn handle(&self, request: &mut Request, next: Next<'_>) -> Result<Response> {
+        self.as_ref().handle(request, next).await
+    }
+
+    fn name(&self) -> &str {
+        self.as_ref().name()
+    }
+}
+
+/// The remainder of the middleware stack, ending at the backend.
+pub struct Next<'a> {
+    middlewares: &'a [Arc<dyn Middleware>],
+    endpoint: &'a (dyn Endpoint + Sync),
+}
+
+impl<'a> Next<'a> {
+    pub(crate) fn new(
+        middlewares: &'a [Arc<dyn Middleware>],
+        endpoint: &'a (dyn Endpoint + Sync),
+    ) -> Self {
+        Self {
+            middlewares,
+            endpoint,
+        }
+    }
+
+    pub async fn run(self, request: &mut Request) -> Result<Response> {
+        match self.middlewares.split_first() {
+            Some((middleware, rest)) => {
+                middleware
+                    .handle(request, Next::new(rest, self.endpoint))
+                    .await
+            }
+            None => Ok(self.endpoint.call_endpoint(request).await?),
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct Middlewares(Vec<Arc<dyn Middleware>>);
+
+impl Middlewares {
+    pub fn push(&mut self, middleware: Arc<dyn Middleware>) {
+        self.0.push(middleware);
+    }
+
+    pub fn insert(&mut self, index: usize, middleware: Arc<dyn Middleware>) {
+        self.0.insert(index.min(self.0.len()), middleware);
+    }
+
+    pub fn replace(&mut self, name: &str, middleware: Arc<dyn Middleware>) -> bool {
+        match self.0.iter_mut().find(|layer| layer.name() == name) {
+            Some(layer) => {
+                *layer = middleware;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.0.iter().map(|layer| layer.name()).collect()
+    }
+
+    pub fn as_slice(&self) -> &[Arc<dyn Middleware>] {
+        &self.0
+    }
+}
+
+impl Debug for Middlewares {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Middlewares").field(&self.names()).finish()
+    }
+}
+
+/// Marks requests built by [`RequestBuilder::dry_run`](crate::RequestBuilder::dry_run) in
+/// their extensions.
+///
+/// Such requests are never sent, so middleware acting outside the request, like sending
+/// shadow traffic or writing an audit log, should leave them alone.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DryRun;
+
+/// An endpoint that keeps the request instead of sending it, used for dry runs.
+#[derive(Debug, Default)]
+pub(crate) struct Capture(Mutex<Option<Request>>);
+
+impl Capture {
+    pub fn into_request(self) -> Option<Request> {
+        self.0.into_inner().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[async_trait]
+impl Endpoint for Capture {
+    async fn call_endpoint(&self, request: &mut Request) -> http_kit::Result<Response> {
+        let request = replace(request, Request::new(Method::GET, "/"));
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = Some(request);
+        Ok(http::Response::new(Body::empty()).into())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Capture, Middleware, Next};
+    use crate::{Client, Result};
+    use async_trait::async_trait;
+    use http_kit::{Body, Method, Request, Response, StatusCode};
+    use hyper::http;
+    use std::sync::{Arc, Mutex};
+
+    type Log = Arc<Mutex<Vec<String>>>;
+
+    /// Logs the request on the way in and the response on the way out.
+    struct Trace(&'static str, Log);
+
+    #[async_trait]
+    impl Middleware for Trace {
+        async fn handle(&self, request: &mut Request, next: Next<'_>) -> Result<Response> {
+            self.1.lock().unwrap().push(format!("> {}", self.0));
+            let response = next.run(request).await;
+            self.1.lock().unwrap().push(format!("< {}", self.0));
+            response
+        }
+    }
+
+    /// Answers without calling the rest of the stack.
+    struct Teapot;
+
+    #[async_trait]
+    impl Middleware for Teapot {
+        async fn handle(&self, _request: &mut Request, _next: Next<'_>) -> Result<Response> {
+            let mut response = http::Response::new(Body::empty());
+            *response.status_mut() = StatusCode::IM_A_TEAPOT;
+            Ok(response.into())
+        }
+    }
+
+    #[tokio::test]
+    async fn next_runs_the_stack_in_order() {
+        let log = Log::default();
+        let layers: [Arc<dyn Middleware>; 2] = [
+            Arc::new(Trace("outer", log.clone())),
+            Arc::new(Trace("inner", log.clone())),
+        ];
+        let endpoint = Capture::default();
+        let mut request = Request::new(Method::GET, "http://example.com/");
+        Next::new(&layers, &endpoint)
+            .run(&mut request)
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *log.lock().unwrap(),
+            ["> outer", "> inner", "< inner", "< outer"]
+        );
+        let sent = endpoint.into_request().unwrap();
+        assert_eq!(sent.uri(), "http://example.com/");
+    }
+
+    #[tokio::test]
+    async fn short_circuit() {
+        let log = Log::default();
+        let layers: [Arc<dyn Middleware>; 2] =
+            [Arc::new(Teapot), Arc::new(Trace("skipped", log.clone()))];
+        let endpoint = Capture::default();
+        let mut request = Request::new(Method::GET, "http://example.com/");
+        let response = Next::new(&layers, &endpoint).run(&mut request).await;
+
+        assert_eq!(response.unwrap().status(), StatusCode::IM_A_TEAPOT);
+        assert!(log.lock().unwrap().is_empty());
+        assert!(endpoint.into_request().is_none(), "the backend was called");
+    }
+
+    #[test]
+    fn layers() {
+        let log = Log::default();
+        let mut client = Client::new()
+            .middleware(Trace("a", log.clone()))
+            .middleware(Arc::new(Teapot));
+        assert_eq!(client.layers(), ["Trace", "Teapot"]);
+
+        client.insert_middleware(usize::MAX, Trace("b", log.clone()));
+        client.insert_middleware(0, Teapot);
+        assert_eq!(client.layers(), ["Teapot", "Trace", "Teapot", "Trace"]);
+
+        assert!(client.replace_middleware("Trace", Teapot));
+        assert!(!client.replace_middleware("Missing", Teapot));
+        assert_eq!(client.layers(), ["Teapot", "Teapot", "Teapot", "Trace"]);
+    }
+}
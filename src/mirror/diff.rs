@@ -0,0 +1,203 @@
+use bytes::Bytes;
+use http_kit::header::{HeaderMap, HeaderName};
+use http_kit::{Response, StatusCode};
+use serde_json::Value;
+
+/// The parts of a response a [`Comparator`] looks at.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    pub status: StatusCode,
+    pub headers: HeaderMap,
+    pub body: Bytes,
+}
+
+impl Snapshot {
+    /// Capture `response`, buffering its body if `body` is set. The body is put back so the
+    /// response can still be read afterwards.
+    pub(crate) async fn capture(response: &mut Response, body: bool) -> http_kit::Result<Self> {
+        let body = if body {
+            let bytes = response.into_bytes().await?;
+            response.replace_body(bytes.clone());
+            bytes
+        } else {
+            Bytes::new()
+        };
+        Ok(Self {
+            status: response.status(),
+            headers: response.headers().clone(),
+            body,
+        })
+    }
+}
+
+/// The differences between a primary and a shadow response.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Diff {
+    /// The primary and shadow status, if they differ.
+    pub status: Option<(StatusCode, StatusCode)>,
+    /// Compared headers whose values differ.
+    pub headers: Vec<HeaderName>,
+    /// JSON pointers to the parts of the body that differ. An empty pointer means the bodies
+    /// differ as a whole.
+    pub body: Vec<String>,
+}
+
+impl Diff {
+    pub fn is_empty(&self) -> bool {
+        self.status.is_none() && self.headers.is_empty() && self.body.is_empty()
+    }
+}
+
+/// Decides which parts of two responses are compared.
+///
+/// By default only the status is compared. Bodies are compared structurally when both parse as
+/// JSON, so key order and formatting don't count as differences, and byte for byte otherwise.
+#[derive(Debug, Clone, Default)]
+pub struct Comparator {
+    headers: Vec<HeaderName>,
+    body: bool,
+    ignore: Vec<String>,
+}
+
+impl Comparator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also compare the values of the `name` header.
+    pub fn header(mut self, name: HeaderName) -> Self {
+        self.headers.push(name);
+        self
+    }
+
+    /// Also compare the bodies.
+    pub fn body(mut self) -> Self {
+        self.body = true;
+        self
+    }
+
+    /// Skip the part of a JSON body at `pointer`, e.g. `/meta/request_id`.
+    pub fn ignore(mut self, pointer: impl Into<String>) -> Self {
+        self.ignore.push(pointer.into());
+        self
+    }
+
+    pub(crate) fn compares_body(&self) -> bool {
+        self.body
+    }
+
+    pub fn diff(&self, primary: &Snapshot, shadow: &Snapshot) -> Diff {
+        let mut diff = Diff::default();
+        if primary.status != shadow.status {
+            diff.status = Some((primary.status, shadow.status));
+        }
+
+        for name in &self.headers {
+            let values = |headers: &HeaderMap| -> Vec<String> {
+                headers
+                    .get_all(name)
+                    .iter()
+                    .map(|value| String::from_utf8_lossy(value.as_bytes()).trim().to_owned())
+                    .collect()
+            };
+            if values(&primary.headers) != values(&shadow.headers) {
+                diff.headers.push(name.clone());
+            }
+        }
+
+        if self.body {
+            let json = serde_json::from_slice::<Value>(&primary.body)
+                .and_then(|primary| Ok((primary, serde_json::from_slice::<Value>(&shadow.body)?)));
+            match json {
+                Ok((primary, shadow)) => self.diff_json(
+                    &mut String::new(),
+                    Some(&primary),
+                    Some(&shadow),
+                    &mut diff.body,
+                ),
+                Err(_) if primary.body != shadow.body => diff.body.push(String::new()),
+                Err(_) => {}
+            }
+        }
+        diff
+    }
+
+    fn diff_json(
+        &self,
+        path: &mut String,
+        primary: Option<&Value>,
+        shadow: Option<&Value>,
+        out: &mut Vec<String>,
+    ) {
+        if self.ignore.iter().any(|ignored| ignored == path) {
+            return;
+        }
+
+        match (primary, shadow) {
+            (Some(Value::Object(primary)), Some(Value::Object(shadow))) => {
+                let keys = primary
+                    .keys()
+                    .chain(shadow.keys().filter(|key| !primary.contains_key(*key)));
+                for key in keys {
+                    let len = path.len();
+                    path.push('/');
+                    path.push_str(&key.replace('~', "~0").replace('/', "~1"));
+                    self.diff_json(path, primary.get(key), shadow.get(key), out);
+                    path.truncate(len);
+                }
+            }
+            (Some(Value::Array(primary)), Some(Value::Array(shadow))) => {
+                for index in 0..primary.len().max(shadow.len()) {
+                    let len = path.len();
+                    path.push('/');
+                    path.push_str(&index.to_string());
+                    self.diff_json(path, primary.get(index), shadow.get(index), out);
+                    path.truncate(len);
+                }
+            }
+            (primary, shadow) => {
+                if primary != shadow {
+                    out.push(path.clone());
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Comparator, Snapshot};
+    use bytes::Bytes;
+    use http_kit::header::HeaderMap;
+    use http_kit::StatusCode;
+
+    fn snapshot(status: StatusCode, body: &'static str) -> Snapshot {
+        Snapshot {
+            status,
+            headers: HeaderMap::new(),
+            body: Bytes::from_static(body.as_bytes()),
+        }
+    }
+
+    #[test]
+    fn json_bodies() {
+        let comparator = Comparator::new().body().ignore("/id");
+        let primary = snapshot(StatusCode::OK, r#"{"id":1,"items":[1,2],"name":"a"}"#);
+        let shadow = snapshot(StatusCode::OK, r#"{"name":"b","items":[1,2,3],"id":2}"#);
+
+        let diff = comparator.diff(&primary, &shadow);
+        assert_eq!(diff.status, None);
+        assert_eq!(diff.body, ["/items/2", "/name"]);
+    }
+
+    #[test]
+    fn opaque_bodies() {
+        let comparator = Comparator::new().body();
+        let primary = snapshot(StatusCode::OK, "hello");
+        let shadow = snapshot(StatusCode::NOT_FOUND, "hello");
+
+        let diff = comparator.diff(&primary, &shadow);
+        assert_eq!(diff.status, Some((StatusCode::OK, StatusCode::NOT_FOUND)));
+        assert!(diff.body.is_empty());
+    }
+}
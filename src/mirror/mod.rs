@@ -0,0 +1,331 @@
+//! Shadow traffic for backend migrations.
+
+mod diff;
+pub use diff::{Comparator, Diff, Snapshot};
+
+use crate::backend::HyperBackend;
+use crate::intern::is_sensitive;
+use crate::uri::rebase;
+use crate::{ClientBackend, DryRun, Middleware, Next, Result, Streaming};
+use async_trait::async_trait;
+use http_kit::{header, Request, Response, Uri};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// Middleware that duplicates a share of requests to a secondary base URI.
+///
+/// Shadow requests are sent in the background once the primary response has arrived; their
+/// responses are only compared against the primary one with a [`Comparator`] and never reach
+/// the caller, and their failures never affect the primary call. [Dry runs](DryRun) and
+/// requests with a [streaming](Streaming) body, which can't be duplicated, aren't mirrored.
+///
+/// Shadow requests leave out credentials such as `Authorization`, `Cookie` or `X-Api-Key`
+/// unless [`forward_credentials`](Mirror::forward_credentials) is set, since the secondary
+/// backend is often less trusted than the primary one. They're copied from the primary
+/// request, [`Overrides`](crate::Overrides) included, and sent straight to the backend from a
+/// background task, so overrides aren't applied to them a second time.
+#[derive(Debug)]
+pub struct Mirror<B = HyperBackend> {
+    base: Uri,
+    ratio: f64,
+    backend: Arc<B>,
+    comparator: Arc<Comparator>,
+    stats: Arc<MirrorStats>,
+    credentials: bool,
+}
+
+impl Mirror {
+    /// Mirror every request to `base`, e.g. `http://new-backend.internal`.
+    pub fn new(base: Uri) -> Self {
+        Self::with_backend(base, HyperBackend::default())
+    }
+}
+
+impl<B: ClientBackend + 'static> Mirror<B> {
+    /// Mirror every request to `base`, sending the shadow requests through `backend`.
+    pub fn with_backend(base: Uri, backend: B) -> Self {
+        Self {
+            base,
+            ratio: 1.0,
+            backend: Arc::new(backend),
+            comparator: Arc::default(),
+            stats: Arc::default(),
+            credentials: false,
+        }
+    }
+
+    /// Only mirror this share of requests, between `0.0` and `1.0`.
+    pub fn ratio(mut self, ratio: f64) -> Self {
+        self.ratio = ratio.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Compare shadow responses with `comparator` instead of by status only.
+    ///
+    /// Comparing bodies requires buffering the primary response body.
+    pub fn compare(mut self, comparator: Comparator) -> Self {
+        self.comparator = Arc::new(comparator);
+        self
+    }
+
+    /// Send the credential headers of the primary request along with shadow requests.
+    pub fn forward_credentials(mut self, forward: bool) -> Self {
+        self.credentials = forward;
+        self
+    }
+
+    /// Comparison counters, shared with the middleware once it's added to a client.
+    pub fn stats(&self) -> Arc<MirrorStats> {
+        self.stats.clone()
+    }
+}
+
+#[async_trait]
+impl<B: ClientBackend + 'static> Middleware for Mirror<B> {
+    async fn handle(&self, request: &mut Request, next: Next<'_>) -> Result<Response> {
+        let extensions = request.extensions();
+        if extensions.get::<DryRun>().is_some() || extensions.get::<Streaming>().is_some() {
+            return next.run(request).await;
+        }
+        let uri = match rebase(&self.base, request.uri()) {
+            Ok(uri) if fastrand::f64() < self.ratio => uri,
+            _ => return next.run(request).await,
+        };
+
+        let body = request.into_bytes().await?;
+        request.replace_body(body.clone());
+
+        let mut shadow = Request::new(request.method().clone(), uri);
+        *shadow.headers_mut() = request.headers().clone();
+        shadow.headers_mut().remove(header::HOST);
+        if !self.credentials {
+            let names: Vec<_> = shadow
+                .headers()
+                .keys()
+                .filter(|name| is_sensitive(name))
+                .cloned()
+                .collect();
+            for name in names {
+                shadow.headers_mut().remove(name);
+            }
+        }
+        shadow.replace_body(body);
+
+        let mut response = next.run(request).await?;
+        let primary = Snapshot::capture(&mut response, self.comparator.compares_body()).await?;
+
+        let backend = self.backend.clone();
+        let comparator = self.comparator.clone();
+        let stats = self.stats.clone();
+        tokio::spawn(async move {
+            stats.mirrored.fetch_add(1, Ordering::Relaxed);
+            let shadow = match backend.call_endpoint(&mut shadow).await {
+                Ok(mut shadow) => Snapshot::capture(&mut shadow, comparator.compares_body()).await,
+                Err(error) => Err(error),
+            };
+            match shadow {
+                Ok(shadow) => stats.record(&comparator.diff(&primary, &shadow)),
+                Err(_) => {
+                    stats.failed.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        });
+
+        Ok(response)
+    }
+}
+
+/// Counters describing how shadow traffic compared to the primary traffic.
+#[derive(Debug, Default)]
+pub struct MirrorStats {
+    mirrored: AtomicU64,
+    failed: AtomicU64,
+    compared: AtomicU64,
+    mismatched: AtomicU64,
+    status: AtomicU64,
+    headers: AtomicU64,
+    body: AtomicU64,
+}
+
+impl MirrorStats {
+    /// Number of requests duplicated to the secondary base URI.
+    pub fn mirrored(&self) -> u64 {
+        self.mirrored.load(Ordering::Relaxed)
+    }
+
+    /// Number of shadow requests that failed outright.
+    pub fn failed(&self) -> u64 {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    /// Number of shadow responses compared against their primary response.
+    pub fn compared(&self) -> u64 {
+        self.compared.load(Ordering::Relaxed)
+    }
+
+    /// Number of shadow responses that differed from their primary response in any way.
+    pub fn mismatched(&self) -> u64 {
+        self.mismatched.load(Ordering::Relaxed)
+    }
+
+    /// Number of shadow responses whose status differed.
+    pub fn status_mismatched(&self) -> u64 {
+        self.status.load(Ordering::Relaxed)
+    }
+
+    /// Number of shadow responses where a compared header differed.
+    pub fn headers_mismatched(&self) -> u64 {
+        self.headers.load(Ordering::Relaxed)
+    }
+
+    /// Number of shadow responses whose body differed.
+    pub fn body_mismatched(&self) -> u64 {
+        self.body.load(Ordering::Relaxed)
+    }
+
+    /// Share of compared shadow responses that differed, between `0.0` and `1.0`.
+    pub fn mismatch_rate(&self) -> f64 {
+        match self.compared() {
+            0 => 0.0,
+            compared => self.mismatched() as f64 / compared as f64,
+        }
+    }
+
+    fn record(&self, diff: &Diff) {
+        self.compared.fetch_add(1, Ordering::Relaxed);
+        if diff.is_empty() {
+            return;
+        }
+        self.mismatched.fetch_add(1, Ordering::Relaxed);
+        for (differs, counter) in [
+            (diff.status.is_some(), &self.status),
+            (!diff.headers.is_empty(), &self.headers),
+            (!diff.body.is_empty(), &self.body),
+        ] {
+            if differs {
+                counter.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{Mirror, MirrorStats};
+    use crate::{Client, ClientBackend};
+    use async_trait::async_trait;
+    use http_kit::header::{self, HeaderMap};
+    use http_kit::{Body, Endpoint, Request, Response, Uri};
+    use hyper::http;
+    use std::sync::{Arc, Mutex};
+
+    /// Answers every request with an empty `200`, remembering what it was sent.
+    #[derive(Debug, Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<(Uri, HeaderMap)>>>);
+
+    impl Recorder {
+        fn requests(&self) -> Vec<(Uri, HeaderMap)> {
+            self.0.lock().unwrap().clone()
+        }
+    }
+
+    #[async_trait]
+    impl Endpoint for Recorder {
+        async fn call_endpoint(&self, request: &mut Request) -> http_kit::Result<Response> {
+            let seen = (request.uri().clone(), request.headers().clone());
+            self.0.lock().unwrap().push(seen);
+            Ok(http::Response::new(Body::empty()).into())
+        }
+    }
+
+    impl ClientBackend for Recorder {}
+
+    /// Waits for the shadow requests in flight to be compared or to fail.
+    async fn settle(stats: &MirrorStats) {
+        for _ in 0..100 {
+            if stats.compared() + stats.failed() == stats.mirrored() {
+                return;
+            }
+            tokio::task::yield_now().await;
+        }
+        panic!("shadow requests didn't complete");
+    }
+
+    #[tokio::test]
+    async fn shadow_request() {
+        let shadow = Recorder::default();
+        let mirror = Mirror::with_backend(Uri::from_static("http://shadow/v2"), shadow.clone());
+        let stats = mirror.stats();
+        let client = Client::with_backend(Recorder::default()).middleware(mirror);
+
+        client
+            .get("http://primary/a?b=1")
+            .header("accept", "text/plain")
+            .header("authorization", "Bearer secret")
+            .header("cookie", "session=1")
+            .header("x-api-key", "secret")
+            .await
+            .unwrap();
+        settle(&stats).await;
+
+        let requests = shadow.requests();
+        let [(uri, headers)] = requests.as_slice() else {
+            panic!("expected one shadow request, got {requests:?}");
+        };
+        assert_eq!(uri, "http://shadow/v2/a?b=1");
+        assert_eq!(headers[header::ACCEPT], "text/plain");
+        for name in ["authorization", "cookie", "x-api-key"] {
+            assert!(!headers.contains_key(name), "{name} was forwarded");
+        }
+        assert_eq!((stats.mirrored(), stats.compared()), (1, 1));
+        assert_eq!(stats.mismatched(), 0);
+    }
+
+    #[tokio::test]
+    async fn forward_credentials() {
+        let shadow = Recorder::default();
+        let mirror = Mirror::with_backend(Uri::from_static("http://shadow"), shadow.clone())
+            .forward_credentials(true);
+        let stats = mirror.stats();
+        let client = Client::with_backend(Recorder::default()).middleware(mirror);
+
+        client
+            .get("http://primary/a")
+            .header("authorization", "Bearer secret")
+            .await
+            .unwrap();
+        settle(&stats).await;
+        assert_eq!(
+            shadow.requests()[0].1[header::AUTHORIZATION],
+            "Bearer secret"
+        );
+    }
+
+    #[tokio::test]
+    async fn ratio() {
+        let shadow = Recorder::default();
+        let mirror =
+            Mirror::with_backend(Uri::from_static("http://shadow"), shadow.clone()).ratio(0.0);
+        let stats = mirror.stats();
+        let primary = Recorder::default();
+        let client = Client::with_backend(primary.clone()).middleware(mirror);
+
+        client.get("http://primary/a").await.unwrap();
+        settle(&stats).await;
+        assert_eq!(primary.requests().len(), 1);
+        assert!(shadow.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn dry_run_isnt_mirrored() {
+        let shadow = Recorder::default();
+        let mirror = Mirror::with_backend(Uri::from_static("http://shadow"), shadow.clone());
+        let stats = mirror.stats();
+        let client = Client::with_backend(Recorder::default()).middleware(mirror);
+
+        client.get("http://primary/a").dry_run().await.unwrap();
+        tokio::task::yield_now().await;
+        assert_eq!(stats.mirrored(), 0);
+        assert!(shadow.requests().is_empty());
+    }
+}
@@ -0,0 +1,154 @@
+use crate::Error;
+use http_kit::{Method, Request, Response, Uri};
+use std::fmt::{self, Debug};
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Callbacks invoked around every request a [`Client`](crate::Client) sends.
+///
+/// Every method does nothing by default, so an observer only implements the events it cares
+/// about. This is the hook for exporting metrics to Prometheus, OpenTelemetry and the like.
+///
+/// Each request calls [`on_request`](ClientObserver::on_request) once, then either
+/// [`on_response`](ClientObserver::on_response) or [`on_error`](ClientObserver::on_error) once.
+/// The client neither follows redirects nor retries, so there are no callbacks or counts for
+/// those; middleware that adds them sends each attempt further down its stack, unobserved.
+pub trait ClientObserver: Send + Sync {
+    /// Called when the request starts, before the client's middleware runs.
+    ///
+    /// Scoped [`Overrides`](crate::Overrides) have been applied, but headers added further
+    /// down, such as cookies, the default `User-Agent` or those set by middleware, aren't part
+    /// of the request yet. Observe the final request with a [`Middleware`](crate::Middleware)
+    /// added last instead.
+    fn on_request(&self, _request: &Request) {}
+
+    /// Called once the response head has been received and has passed back through the
+    /// middleware. `elapsed` runs from [`on_request`](ClientObserver::on_request).
+    fn on_response(&self, _method: &Method, _uri: &Uri, _response: &Response, _elapsed: Duration) {}
+
+    /// Called when the request fails.
+    fn on_error(&self, _method: &Method, _uri: &Uri, _error: &Error, _elapsed: Duration) {}
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct Observers(Vec<Arc<dyn ClientObserver>>);
+
+impl Observers {
+    pub fn push(&mut self, observer: Arc<dyn ClientObserver>) {
+        self.0.push(observer);
+    }
+
+    pub fn request(&self, request: &Request) {
+        for observer in &self.0 {
+            observer.on_request(request);
+        }
+    }
+
+    pub fn finish(
+        &self,
+        method: &Method,
+        uri: &Uri,
+        result: &crate::Result<Response>,
+        elapsed: Duration,
+    ) {
+        for observer in &self.0 {
+            match result {
+                Ok(response) => observer.on_response(method, uri, response, elapsed),
+                Err(error) => observer.on_error(method, uri, error, elapsed),
+            }
+        }
+    }
+}
+
+impl Debug for Observers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Observers").field(&self.0.len()).finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ClientObserver;
+    use crate::{Client, ClientBackend, Error, Middleware, Next, Result};
+    use async_trait::async_trait;
+    use http_kit::{Body, Endpoint, Method, Request, Response, Uri};
+    use hyper::http;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    /// The events of a request, in the order they happened.
+    #[derive(Clone, Default)]
+    struct Events(Arc<Mutex<Vec<String>>>);
+
+    impl Events {
+        fn push(&self, event: impl Into<String>) {
+            self.0.lock().unwrap().push(event.into());
+        }
+
+        fn take(&self) -> Vec<String> {
+            std::mem::take(&mut *self.0.lock().unwrap())
+        }
+    }
+
+    impl ClientObserver for Events {
+        fn on_request(&self, request: &Request) {
+            self.push(format!("request {}", request.uri().path()));
+        }
+
+        fn on_response(&self, _method: &Method, uri: &Uri, response: &Response, _: Duration) {
+            self.push(format!(
+                "response {} {}",
+                uri.path(),
+                response.status().as_u16()
+            ));
+        }
+
+        fn on_error(&self, _method: &Method, uri: &Uri, _error: &Error, _elapsed: Duration) {
+            self.push(format!("error {}", uri.path()));
+        }
+    }
+
+    #[async_trait]
+    impl Middleware for Events {
+        async fn handle(&self, request: &mut Request, next: Next<'_>) -> Result<Response> {
+            self.push("middleware");
+            next.run(request).await
+        }
+    }
+
+    /// Fails `/fail` and answers anything else with an empty `200`.
+    struct Backend;
+
+    #[async_trait]
+    impl Endpoint for Backend {
+        async fn call_endpoint(&self, request: &mut Request) -> http_kit::Result<Response> {
+            if request.uri().path() == "/fail" {
+                return Err(io::Error::from(io::ErrorKind::ConnectionRefused).into());
+            }
+            Ok(http::Response::new(Body::empty()).into())
+        }
+    }
+
+    impl ClientBackend for Backend {}
+
+    #[tokio::test]
+    async fn order() {
+        let events = Events::default();
+        let client = Client::with_backend(Backend)
+            .observer(events.clone())
+            .middleware(events.clone());
+
+        client.get("http://example.com/ok").await.unwrap();
+        assert_eq!(
+            events.take(),
+            ["request /ok", "middleware", "response /ok 200"]
+        );
+
+        assert!(client.get("http://example.com/fail").await.is_err());
+        assert_eq!(
+            events.take(),
+            ["request /fail", "middleware", "error /fail"]
+        );
+    }
+}
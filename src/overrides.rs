@@ -0,0 +1,180 @@
+use crate::uri::{origin, rebase};
+use crate::{Error, Result};
+use http_kit::header::{self, HeaderMap, HeaderName, HeaderValue};
+use http_kit::{Request, Uri};
+use std::future::Future;
+use std::sync::Arc;
+
+tokio::task_local! {
+    static OVERRIDES: Arc<Overrides>;
+}
+
+/// Policies applied to every request sent within [`Overrides::scope`], by any client.
+///
+/// This lets multi-tenant services pick the downstream base URI and credentials once per
+/// inbound request instead of threading them through every call site.
+///
+/// Overrides are bound to the task running [`Overrides::scope`]. They're carried into tasks
+/// spawned with [`RequestScope::spawn`](crate::RequestScope::spawn), but not into those
+/// spawned with `tokio::spawn`, which need a scope of their own.
+#[derive(Debug, Clone, Default)]
+pub struct Overrides {
+    base: Option<Uri>,
+    headers: HeaderMap,
+    /// `Authorization` values by the origin they may be sent to.
+    authorization: Vec<(String, HeaderValue)>,
+}
+
+impl Overrides {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Send requests to `base`, keeping their own path and query.
+    pub fn base(mut self, base: Uri) -> Self {
+        self.base = Some(base);
+        self
+    }
+
+    /// Set the `name` header on every request, replacing any value it already has.
+    ///
+    /// The header is sent to every host, so credentials belong in
+    /// [`authorization`](Overrides::authorization) instead.
+    pub fn header(mut self, name: HeaderName, value: HeaderValue) -> Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// Send `value` as the `Authorization` header of requests to the origin of `uri`, that is
+    /// its scheme, host and port, and of no others.
+    pub fn authorization(mut self, uri: &Uri, mut value: HeaderValue) -> Self {
+        let origin = origin(uri);
+        value.set_sensitive(true);
+        self.authorization
+            .retain(|(registered, _)| *registered != origin);
+        self.authorization.push((origin, value));
+        self
+    }
+
+    /// Run `future` with these overrides in effect.
+    ///
+    /// Scopes don't merge: within a nested scope only the innermost overrides apply.
+    pub async fn scope<F: Future>(self, future: F) -> F::Output {
+        OVERRIDES.scope(Arc::new(self), future).await
+    }
+
+    pub(crate) fn apply(request: &mut Request) -> Result<()> {
+        OVERRIDES
+            .try_with(|overrides| overrides.apply_to(request))
+            .unwrap_or(Ok(()))
+    }
+
+    /// Run `future` with the overrides in effect now, for tasks spawned from this one.
+    pub(crate) fn carry<F: Future>(future: F) -> impl Future<Output = F::Output> {
+        let overrides = OVERRIDES.try_with(Arc::clone).ok();
+        async move {
+            match overrides {
+                Some(overrides) => OVERRIDES.scope(overrides, future).await,
+                None => future.await,
+            }
+        }
+    }
+
+    fn apply_to(&self, request: &mut Request) -> Result<()> {
+        if let Some(base) = &self.base {
+            *request.uri_mut() = rebase(base, request.uri()).map_err(Error::InvalidUri)?;
+        }
+        for (name, value) in &self.headers {
+            request.insert_header(name.clone(), value.clone());
+        }
+        if !self.authorization.is_empty() {
+            let target = origin(request.uri());
+            let value = self
+                .authorization
+                .iter()
+                .find(|(origin, _)| *origin == target);
+            if let Some((_, value)) = value {
+                request.insert_header(header::AUTHORIZATION, value.clone());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Overrides;
+    use crate::RequestScope;
+    use http_kit::header::{self, HeaderName, HeaderValue};
+    use http_kit::{Method, Request, Uri};
+
+    fn applied(uri: &str) -> Request {
+        let mut request = Request::new(Method::GET, uri);
+        Overrides::apply(&mut request).unwrap();
+        request
+    }
+
+    #[tokio::test]
+    async fn base_and_headers() {
+        let overrides = Overrides::new()
+            .base(Uri::from_static("http://tenant.internal/v2"))
+            .header(
+                HeaderName::from_static("x-tenant"),
+                HeaderValue::from_static("a"),
+            );
+        let request = overrides
+            .scope(async { applied("http://example.com/users?page=2") })
+            .await;
+        assert_eq!(request.uri(), "http://tenant.internal/v2/users?page=2");
+        assert_eq!(request.headers()["x-tenant"], "a");
+
+        // Outside of the scope nothing changes.
+        let request = applied("http://example.com/users");
+        assert_eq!(request.uri(), "http://example.com/users");
+        assert!(!request.headers().contains_key("x-tenant"));
+    }
+
+    #[tokio::test]
+    async fn authorization_is_limited_to_its_origin() {
+        let overrides = Overrides::new().authorization(
+            &Uri::from_static("https://API.example.com/"),
+            HeaderValue::from_static("Bearer secret"),
+        );
+        let requests = overrides
+            .scope(async {
+                [
+                    "https://api.example.com:443/users",
+                    "https://user@api.example.com/users",
+                    "http://api.example.com/users",
+                    "https://api.example.com:8443/users",
+                    "https://evil.example.com/users",
+                ]
+                .map(applied)
+            })
+            .await;
+        let sent: Vec<bool> = requests
+            .iter()
+            .map(|request| request.headers().contains_key(header::AUTHORIZATION))
+            .collect();
+        assert_eq!(sent, [true, true, false, false, false]);
+        assert!(requests[0].headers()[header::AUTHORIZATION].is_sensitive());
+    }
+
+    #[tokio::test]
+    async fn carried_into_scoped_tasks() {
+        let mut scope = RequestScope::new();
+        let overrides = Overrides::new().header(
+            HeaderName::from_static("x-tenant"),
+            HeaderValue::from_static("a"),
+        );
+        overrides
+            .scope(async {
+                scope.spawn(async {
+                    let request = applied("http://example.com/");
+                    request.headers().get("x-tenant").cloned()
+                });
+            })
+            .await;
+        assert_eq!(scope.join().await, [Some(HeaderValue::from_static("a"))]);
+    }
+}
@@ -0,0 +1,194 @@
+//! Downloads that survive a dropped connection.
+
+use crate::ClientBackend;
+use async_trait::async_trait;
+use bytes::Bytes;
+use futures_util::stream::{self, StreamExt};
+use http_kit::header::{self, HeaderMap, HeaderValue};
+use http_kit::{Body, Endpoint, Method, Request, Response, StatusCode, Uri};
+use std::io;
+use std::sync::Arc;
+
+/// A backend wrapper resuming `GET` response bodies that fail midway.
+///
+/// When the server supports byte ranges and identifies the resource with a strong `ETag` or a
+/// `Last-Modified` date, a body that breaks off is requested again from the last byte received,
+/// with `If-Range` guarding against the resource having changed in between. The caller keeps
+/// reading the same body and never sees the interruption; once the attempts are exhausted or
+/// the server can't resume, the original error surfaces.
+///
+/// It wraps the backend of a client, e.g. `Client::with_backend(Resume::new(backend))`, so
+/// resumed requests carry the headers the middleware stack set, such as cookies or
+/// credentials, and go through the same connector as the original one.
+#[derive(Debug)]
+pub struct Resume<B> {
+    backend: Arc<B>,
+    attempts: u32,
+}
+
+impl<B: ClientBackend + 'static> Resume<B> {
+    pub fn new(backend: B) -> Self {
+        Self {
+            backend: Arc::new(backend),
+            attempts: 3,
+        }
+    }
+
+    /// Resume a body at most `attempts` times. Defaults to 3.
+    pub fn attempts(mut self, attempts: u32) -> Self {
+        self.attempts = attempts;
+        self
+    }
+}
+
+#[async_trait]
+impl<B: ClientBackend + 'static> Endpoint for Resume<B> {
+    async fn call_endpoint(&self, request: &mut Request) -> http_kit::Result<Response> {
+        let resumable =
+            request.method() == Method::GET && !request.headers().contains_key(header::RANGE);
+        let uri = request.uri().clone();
+        let headers = request.headers().clone();
+        let mut response = self.backend.call_endpoint(request).await?;
+        if !resumable || response.status() != StatusCode::OK {
+            return Ok(response);
+        }
+
+        let ranges = response
+            .headers()
+            .get(header::ACCEPT_RANGES)
+            .is_some_and(|ranges| ranges.as_bytes().eq_ignore_ascii_case(b"bytes"));
+        let Some(validator) = validator(response.headers()) else {
+            return Ok(response);
+        };
+        if !ranges {
+            return Ok(response);
+        }
+
+        let download = Download {
+            backend: self.backend.clone(),
+            uri,
+            headers,
+            validator,
+            body: Some(response.replace_body(Body::empty())),
+            offset: 0,
+            attempts: self.attempts,
+        };
+        response.replace_body(Body::from_stream(stream::unfold(download, Download::next)));
+        Ok(response)
+    }
+}
+
+impl<B: ClientBackend + 'static> ClientBackend for Resume<B> {
+    fn system_connector(&self) -> bool {
+        self.backend.system_connector()
+    }
+}
+
+/// A strong `ETag`, or else `Last-Modified`; weak validators can't be used with `If-Range`.
+fn validator(headers: &HeaderMap) -> Option<HeaderValue> {
+    match headers.get(header::ETAG) {
+        Some(etag) if !etag.as_bytes().starts_with(b"W/") => Some(etag.clone()),
+        _ => headers.get(header::LAST_MODIFIED).cloned(),
+    }
+}
+
+struct Download<B> {
+    backend: Arc<B>,
+    uri: Uri,
+    headers: HeaderMap,
+    validator: HeaderValue,
+    /// `None` once the body has ended or failed for good.
+    body: Option<Body>,
+    offset: u64,
+    attempts: u32,
+}
+
+impl<B: ClientBackend> Download<B> {
+    async fn next(mut self) -> Option<(io::Result<Bytes>, Self)> {
+        loop {
+            let error = match self.body.as_mut()?.next().await {
+                Some(Ok(chunk)) => {
+                    self.offset += chunk.len() as u64;
+                    return Some((Ok(chunk), self));
+                }
+                Some(Err(error)) => io::Error::other(error.to_string()),
+                None => return None,
+            };
+
+            self.body = None;
+            while self.attempts > 0 && self.body.is_none() {
+                self.attempts -= 1;
+                self.body = self.reconnect().await;
+            }
+            if self.body.is_none() {
+                return Some((Err(error), self));
+            }
+        }
+    }
+
+    async fn reconnect(&self) -> Option<Body> {
+        let mut request = Request::new(Method::GET, self.uri.clone());
+        *request.headers_mut() = self.headers.clone();
+        let range = HeaderValue::try_from(format!("bytes={}-", self.offset)).ok()?;
+        request.insert_header(header::RANGE, range);
+        request.insert_header(header::IF_RANGE, self.validator.clone());
+
+        let mut response = self.backend.call_endpoint(&mut request).await.ok()?;
+        // A changed resource is sent in full with `200 OK` instead.
+        if response.status() != StatusCode::PARTIAL_CONTENT {
+            return None;
+        }
+        let start = format!("bytes {}-", self.offset);
+        let range = response.headers().get(header::CONTENT_RANGE)?;
+        if !range.as_bytes().starts_with(start.as_bytes()) {
+            return None;
+        }
+        Some(response.replace_body(Body::empty()))
+    }
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod test {
+    use super::Resume;
+    use crate::backend::HyperBackend;
+    use crate::test_util::{test_server, Truncate};
+    use crate::Client;
+    use hyper::http::{header, Method, Response, StatusCode};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    const BODY: &[u8] = b"0123456789";
+
+    #[tokio::test]
+    async fn resumes_a_truncated_body() {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let counter = requests.clone();
+        let server = test_server()
+            .route(Method::GET, "/file", move |request| {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let response = Response::builder()
+                    .header(header::ACCEPT_RANGES, "bytes")
+                    .header(header::ETAG, "\"v1\"");
+                match request.headers().get(header::RANGE) {
+                    None => response.extension(Truncate(4)).body(BODY.into()).unwrap(),
+                    Some(range) => {
+                        assert_eq!(range, "bytes=4-");
+                        assert_eq!(request.headers()[header::IF_RANGE], "\"v1\"");
+                        assert_eq!(request.headers()["x-token"], "1", "headers are kept");
+                        response
+                            .status(StatusCode::PARTIAL_CONTENT)
+                            .header(header::CONTENT_RANGE, "bytes 4-9/10")
+                            .body(BODY[4..].into())
+                            .unwrap()
+                    }
+                }
+            })
+            .start();
+
+        let client = Client::with_backend(Resume::new(HyperBackend::new()));
+        let response = client.get(server.url("/file")).header("x-token", "1");
+        let body = response.await.unwrap().into_bytes().await.unwrap();
+        assert_eq!(body, BODY);
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+}
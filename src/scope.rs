@@ -0,0 +1,110 @@
+use crate::{AbortHandle, Overrides};
+use std::future::Future;
+use std::panic::resume_unwind;
+use std::sync::{Mutex, PoisonError};
+use tokio::task::JoinSet;
+
+/// Requests and tasks tied to the lifetime of a scope, such as the fan-out calls of a server
+/// handler.
+///
+/// Requests join with [`RequestBuilder::scope`](crate::RequestBuilder::scope) and tasks with
+/// [`RequestScope::spawn`]. Dropping the scope, for instance because the inbound request was
+/// cancelled, aborts every request and task still running; [`RequestScope::join`] waits for the
+/// tasks to finish instead.
+#[derive(Debug)]
+pub struct RequestScope<T = ()> {
+    handles: Mutex<Vec<AbortHandle>>,
+    tasks: JoinSet<T>,
+}
+
+impl<T: Send + 'static> RequestScope<T> {
+    pub fn new() -> Self {
+        Self {
+            handles: Mutex::default(),
+            tasks: JoinSet::new(),
+        }
+    }
+
+    pub(crate) fn track(&self, handle: AbortHandle) {
+        let mut handles = self.handles.lock().unwrap_or_else(PoisonError::into_inner);
+        handles.retain(AbortHandle::in_use);
+        handles.push(handle);
+    }
+
+    /// Run `task` on the Tokio runtime until it completes or the scope ends.
+    ///
+    /// Requests of a `'static` client, e.g. [`get`](crate::get), can be awaited in it. The
+    /// [`Overrides`] in effect where the task is spawned apply to it as well.
+    pub fn spawn(&mut self, task: impl Future<Output = T> + Send + 'static) {
+        self.tasks.spawn(Overrides::carry(task));
+    }
+
+    /// Abort every request and task of the scope.
+    pub fn cancel(&mut self) {
+        let handles = self
+            .handles
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        for handle in handles.drain(..) {
+            handle.abort();
+        }
+        self.tasks.abort_all();
+    }
+
+    /// Wait for every spawned task, returning their outputs in the order they completed.
+    ///
+    /// A panic in a task is resumed here.
+    pub async fn join(mut self) -> Vec<T> {
+        let mut outputs = Vec::with_capacity(self.tasks.len());
+        while let Some(output) = self.tasks.join_next().await {
+            match output {
+                Ok(output) => outputs.push(output),
+                Err(error) => resume_unwind(error.into_panic()),
+            }
+        }
+        outputs
+    }
+}
+
+impl<T: Send + 'static> Default for RequestScope<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Drop for RequestScope<T> {
+    fn drop(&mut self) {
+        let handles = self
+            .handles
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner);
+        for handle in handles.drain(..) {
+            handle.abort();
+        }
+        // Dropping the `JoinSet` aborts the tasks.
+    }
+}
+
+#[cfg(all(test, feature = "test-util"))]
+mod test {
+    use super::RequestScope;
+    use crate::test_util::test_server;
+    use hyper::http::{Method, Response};
+
+    #[tokio::test]
+    async fn spawn_requests() {
+        let server = test_server()
+            .route(Method::GET, "/", |_| Response::new("ok".into()))
+            .start();
+
+        let mut scope = RequestScope::new();
+        for _ in 0..3 {
+            let url = server.url("/");
+            scope.spawn(async move {
+                let mut response = crate::get(url).await.unwrap();
+                response.into_string().await.unwrap()
+            });
+        }
+        assert_eq!(scope.join().await, ["ok", "ok", "ok"]);
+    }
+}
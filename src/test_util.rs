@@ -0,0 +1,214 @@
+//! A local HTTP server for end-to-end tests.
+//!
+//! The server only speaks plain HTTP. The client has no TLS backend to test against, so an
+//! HTTPS fixture is left out until one is added.
+
+use bytes::Bytes;
+use futures_util::stream;
+use hyper::http::{header, Method, Request, Response, StatusCode};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Server};
+use std::convert::Infallible;
+use std::fmt::{self, Debug};
+use std::io;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
+use tokio::sync::oneshot;
+
+type Handler = Arc<dyn Fn(Request<Bytes>) -> Response<Bytes> + Send + Sync>;
+
+/// Start describing a [`TestServer`]: add routes, then [`start`](Routes::start) it.
+pub fn test_server() -> Routes {
+    Routes::default()
+}
+
+/// The routes of a [`TestServer`], each answered by a closure.
+#[derive(Clone, Default)]
+pub struct Routes {
+    routes: Vec<(Method, String, Handler)>,
+}
+
+impl Routes {
+    /// Answer `method` requests for exactly `path` with `handler`, which gets the buffered
+    /// request. The response body can be cut short with [`Truncate`].
+    pub fn route(
+        mut self,
+        method: Method,
+        path: &str,
+        handler: impl Fn(Request<Bytes>) -> Response<Bytes> + Send + Sync + 'static,
+    ) -> Self {
+        self.routes
+            .push((method, path.to_owned(), Arc::new(handler)));
+        self
+    }
+
+    /// Serve the routes on an ephemeral port of the loopback interface.
+    ///
+    /// Must be called within a Tokio runtime. Requests no route matches get a `404`.
+    ///
+    /// # Panics
+    ///
+    /// If no port can be bound.
+    pub fn start(self) -> TestServer {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("failed to bind a test server");
+        listener
+            .set_nonblocking(true)
+            .expect("failed to configure the test server socket");
+        let addr = listener
+            .local_addr()
+            .expect("bound sockets have an address");
+
+        let routes = Arc::new(self.routes);
+        let make_service = make_service_fn(move |_| {
+            let routes = routes.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let routes = routes.clone();
+                    async move { respond(&routes, request).await }
+                }))
+            }
+        });
+        let (shutdown, signal) = oneshot::channel::<()>();
+        let server = Server::from_tcp(listener)
+            .expect("failed to start a test server")
+            .serve(make_service)
+            .with_graceful_shutdown(async {
+                let _ = signal.await;
+            });
+        tokio::spawn(server);
+
+        TestServer {
+            addr,
+            shutdown: Some(shutdown),
+        }
+    }
+}
+
+impl Debug for Routes {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let routes: Vec<String> = self
+            .routes
+            .iter()
+            .map(|(method, path, _)| format!("{method} {path}"))
+            .collect();
+        f.debug_tuple("Routes").field(&routes).finish()
+    }
+}
+
+async fn respond(
+    routes: &[(Method, String, Handler)],
+    request: Request<Body>,
+) -> Result<Response<Body>, hyper::Error> {
+    let (parts, body) = request.into_parts();
+    let body = hyper::body::to_bytes(body).await?;
+    let request = Request::from_parts(parts, body);
+
+    let handler = routes
+        .iter()
+        .find(|(method, path, _)| method == request.method() && path == request.uri().path());
+    let response = match handler {
+        Some((_, _, handler)) => {
+            let mut response = handler(request);
+            match response.extensions_mut().remove::<Truncate>() {
+                Some(Truncate(length)) => truncate(response, length),
+                None => response.map(Body::from),
+            }
+        }
+        None => {
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = StatusCode::NOT_FOUND;
+            response
+        }
+    };
+    Ok(response)
+}
+
+/// Put in the extensions of a response to send only its first bytes, then drop the
+/// connection.
+///
+/// The `Content-Length` of the whole body is sent, so clients see the body fail midway.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Truncate(pub usize);
+
+fn truncate(response: Response<Bytes>, length: usize) -> Response<Body> {
+    let (mut parts, body) = response.into_parts();
+    parts
+        .headers
+        .insert(header::CONTENT_LENGTH, body.len().into());
+    let chunks = [
+        Ok(body.slice(..length.min(body.len()))),
+        Err(io::Error::new(
+            io::ErrorKind::ConnectionAborted,
+            "truncated",
+        )),
+    ];
+    Response::from_parts(parts, Body::wrap_stream(stream::iter(chunks)))
+}
+
+/// A running local server, shut down when dropped.
+///
+/// Only plain HTTP is served, see the [module documentation](self).
+#[derive(Debug)]
+pub struct TestServer {
+    addr: SocketAddr,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl TestServer {
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The base URL, e.g. `http://127.0.0.1:38501`.
+    pub fn base_url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// The URL of `path` on this server.
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{path}", self.base_url())
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::test_server;
+    use crate::Client;
+    use hyper::http::{header, Method, Response};
+
+    #[tokio::test]
+    async fn cookies_round_trip() {
+        let server = test_server()
+            .route(Method::GET, "/login", |_| {
+                Response::builder()
+                    .header(header::SET_COOKIE, "session=1; Path=/")
+                    .body("welcome".into())
+                    .unwrap()
+            })
+            .route(Method::GET, "/me", |request| {
+                let cookie = request.headers().get(header::COOKIE).cloned();
+                let body = cookie.map_or_else(Default::default, |cookie| {
+                    bytes::Bytes::copy_from_slice(cookie.as_bytes())
+                });
+                Response::new(body)
+            })
+            .start();
+
+        let mut client = Client::new();
+        client.enable_cookie_store();
+        client.get(server.url("/login")).await.unwrap();
+        let mut response = client.get(server.url("/me")).await.unwrap();
+        assert_eq!(response.into_string().await.unwrap(), "session=1");
+
+        let response = client.get(server.url("/missing")).await.unwrap();
+        assert_eq!(response.status(), 404);
+    }
+}
@@ -0,0 +1,438 @@
+use crate::{Error, Result};
+use http_kit::Uri;
+use hyper::http;
+use std::borrow::Cow;
+use std::fmt::{self, Display};
+
+/// Point `uri` at `base`: the scheme and authority are replaced and the path of `base` is
+/// prepended, while the path and query of `uri` are kept.
+pub(crate) fn rebase(base: &Uri, uri: &Uri) -> std::result::Result<Uri, http::Error> {
+    let path = format!(
+        "{}{}",
+        base.path().trim_end_matches('/'),
+        uri.path_and_query().map_or("/", |path| path.as_str())
+    );
+    let mut parts = base.clone().into_parts();
+    parts.path_and_query = Some(path.try_into()?);
+    Ok(Uri::from_parts(parts)?)
+}
+
+/// The origin of `uri` as `scheme://host:port`, the port defaulting to that of the scheme, so
+/// that URIs of the same origin compare equal however they're spelled.
+pub(crate) fn origin(uri: &Uri) -> String {
+    let scheme = uri.scheme_str().unwrap_or("http").to_ascii_lowercase();
+    let port = uri
+        .port_u16()
+        .unwrap_or(if scheme == "https" { 443 } else { 80 });
+    let host = uri.host().unwrap_or_default().to_ascii_lowercase();
+    format!("{scheme}://{host}:{port}")
+}
+
+/// `uri` without its user info and with the value of every query parameter replaced with
+/// `REDACTED`, so it can be logged.
+pub(crate) fn redact(uri: &Uri) -> Uri {
+    redact_with(uri, |_| Some("REDACTED".into()))
+}
+
+/// `uri` without its user info and with the value of every query parameter replaced with what
+/// `replace` returns for it, or kept if it returns `None`.
+pub(crate) fn redact_with(uri: &Uri, replace: impl Fn(&str) -> Option<String>) -> Uri {
+    let mut parts = uri.clone().into_parts();
+    if let Some(authority) = &parts.authority {
+        if let Some((_, host)) = authority.as_str().rsplit_once('@') {
+            parts.authority = host.parse().ok();
+        }
+    }
+    if let Some(query) = uri.query() {
+        let pairs: Vec<String> = query
+            .split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((name, value)) => match replace(value) {
+                    Some(value) => format!("{name}={value}"),
+                    None => pair.to_owned(),
+                },
+                None => pair.to_owned(),
+            })
+            .collect();
+        let path = format!("{}?{}", uri.path(), pairs.join("&"));
+        parts.path_and_query = path.parse().ok();
+    }
+    // Only the parts of a valid URI are reassembled, so this never falls back in practice.
+    Uri::from_parts(parts).unwrap_or_default()
+}
+
+/// Something a request can be sent to: anything [`Uri`] can be converted from.
+///
+/// Without a [`UrlParser`] this is the conversion of [`Uri`] itself. Once the client has one,
+/// URLs given as text go through it instead.
+pub trait IntoUri {
+    fn into_uri(self, parser: Option<&UrlParser>) -> Result<Uri>;
+}
+
+fn convert<T>(uri: T) -> Result<Uri>
+where
+    Uri: TryFrom<T>,
+    <Uri as TryFrom<T>>::Error: Into<http::Error>,
+{
+    Uri::try_from(uri).map_err(|error| Error::InvalidUri(error.into()))
+}
+
+impl IntoUri for Uri {
+    fn into_uri(self, _parser: Option<&UrlParser>) -> Result<Uri> {
+        Ok(self)
+    }
+}
+
+impl IntoUri for &Uri {
+    fn into_uri(self, _parser: Option<&UrlParser>) -> Result<Uri> {
+        Ok(self.clone())
+    }
+}
+
+impl IntoUri for http::uri::Parts {
+    fn into_uri(self, _parser: Option<&UrlParser>) -> Result<Uri> {
+        convert(self)
+    }
+}
+
+impl IntoUri for &str {
+    fn into_uri(self, parser: Option<&UrlParser>) -> Result<Uri> {
+        match parser {
+            Some(parser) => parser.parse(self),
+            None => convert(self),
+        }
+    }
+}
+
+impl IntoUri for String {
+    fn into_uri(self, parser: Option<&UrlParser>) -> Result<Uri> {
+        match parser {
+            Some(parser) => parser.parse(&self),
+            None => convert(self),
+        }
+    }
+}
+
+impl IntoUri for &String {
+    fn into_uri(self, parser: Option<&UrlParser>) -> Result<Uri> {
+        self.as_str().into_uri(parser)
+    }
+}
+
+impl IntoUri for Cow<'_, str> {
+    fn into_uri(self, parser: Option<&UrlParser>) -> Result<Uri> {
+        match self {
+            Cow::Borrowed(uri) => uri.into_uri(parser),
+            Cow::Owned(uri) => uri.into_uri(parser),
+        }
+    }
+}
+
+impl IntoUri for &[u8] {
+    fn into_uri(self, parser: Option<&UrlParser>) -> Result<Uri> {
+        match (parser, std::str::from_utf8(self)) {
+            (Some(parser), Ok(uri)) => parser.parse(uri),
+            _ => convert(self),
+        }
+    }
+}
+
+impl IntoUri for Vec<u8> {
+    fn into_uri(self, parser: Option<&UrlParser>) -> Result<Uri> {
+        match parser {
+            Some(_) => self.as_slice().into_uri(parser),
+            None => convert(self),
+        }
+    }
+}
+
+/// What's wrong with a URL, see [`UrlError`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum UrlErrorKind {
+    Empty,
+    /// There's no `scheme://` in front, and no default scheme was configured.
+    MissingScheme,
+    InvalidScheme,
+    EmptyHost,
+    /// An IP literal in brackets isn't an IPv6 address.
+    InvalidHost,
+    /// The port isn't a number up to 65535.
+    InvalidPort,
+    /// A character URLs can't contain, such as a space, unless percent-encoded.
+    IllegalCharacter(char),
+    /// A `%` that isn't followed by two hexadecimal digits.
+    InvalidPercentEncoding,
+}
+
+/// A URL that doesn't follow RFC 3986, and where it goes wrong.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UrlError {
+    pub kind: UrlErrorKind,
+    /// Byte offset into the URL as given.
+    pub position: usize,
+}
+
+impl Display for UrlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            UrlErrorKind::Empty => return f.write_str("empty url"),
+            UrlErrorKind::MissingScheme => f.write_str("missing scheme")?,
+            UrlErrorKind::InvalidScheme => f.write_str("invalid scheme")?,
+            UrlErrorKind::EmptyHost => f.write_str("empty host")?,
+            UrlErrorKind::InvalidHost => f.write_str("invalid host")?,
+            UrlErrorKind::InvalidPort => f.write_str("invalid port")?,
+            UrlErrorKind::IllegalCharacter(c) => write!(f, "illegal character {c:?}")?,
+            UrlErrorKind::InvalidPercentEncoding => f.write_str("invalid percent-encoding")?,
+        }
+        write!(f, " at position {}", self.position)
+    }
+}
+
+impl std::error::Error for UrlError {}
+
+/// Parses URLs given as strings, reporting exactly what's wrong with invalid ones.
+///
+/// Clients only use one once it's set with [`Client::url_parser`](crate::Client::url_parser);
+/// it then requires absolute URLs. By default they must follow RFC 3986 to the letter. Common
+/// mistakes of hand-typed URLs can be fixed instead with [`UrlParser::fix`] and
+/// [`UrlParser::default_scheme`].
+#[derive(Debug, Clone, Default)]
+pub struct UrlParser {
+    default_scheme: Option<String>,
+    fix: bool,
+}
+
+impl UrlParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Prepend `scheme://` to URLs without a scheme, e.g. `https` for `example.com/path`.
+    pub fn default_scheme(mut self, scheme: &str) -> Self {
+        self.default_scheme = Some(scheme.to_owned());
+        self
+    }
+
+    /// Trim surrounding whitespace, and percent-encode characters that aren't allowed in the
+    /// path, query or fragment, such as spaces.
+    pub fn fix(mut self) -> Self {
+        self.fix = true;
+        self
+    }
+
+    /// Parse `input`, failing with [`Error::InvalidUrl`].
+    pub fn parse(&self, input: &str) -> Result<Uri> {
+        let url = self.check(input).map_err(Error::InvalidUrl)?;
+        Uri::try_from(url).map_err(|error| Error::InvalidUri(error.into()))
+    }
+
+    /// Validate `input`, returning it with the fixes applied.
+    fn check(&self, input: &str) -> std::result::Result<String, UrlError> {
+        let error = |kind, position| UrlError { kind, position };
+        let (lead, text) = if self.fix {
+            let trimmed = input.trim_start();
+            (input.len() - trimmed.len(), trimmed.trim_end())
+        } else {
+            (0, input)
+        };
+        if text.is_empty() {
+            return Err(error(UrlErrorKind::Empty, 0));
+        }
+
+        // A `://` in the path or query, e.g. `?next=http://...`, doesn't end a scheme.
+        let scheme_end = text
+            .find("://")
+            .filter(|&end| !text[..end].contains(['/', '?', '#']));
+        let (scheme, rest, offset) = match scheme_end {
+            Some(end) => (&text[..end], &text[end + 3..], lead + end + 3),
+            None => match &self.default_scheme {
+                Some(scheme) => (scheme.as_str(), text, lead),
+                None => return Err(error(UrlErrorKind::MissingScheme, lead)),
+            },
+        };
+        let mut chars = scheme.char_indices();
+        let valid_start = chars.next().is_some_and(|(_, c)| c.is_ascii_alphabetic());
+        if !valid_start {
+            return Err(error(UrlErrorKind::InvalidScheme, lead));
+        }
+        if let Some((index, _)) =
+            chars.find(|(_, c)| !(c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.')))
+        {
+            return Err(error(UrlErrorKind::InvalidScheme, lead + index));
+        }
+
+        let end = rest.find(['/', '?', '#']).unwrap_or(rest.len());
+        check_authority(&rest[..end], offset)?;
+
+        let mut url = format!("{scheme}://{}", &rest[..end]);
+        let mut fragment = false;
+        let path = &rest[end..];
+        for (index, c) in path.char_indices() {
+            let position = offset + end + index;
+            let allowed = match c {
+                '%' => {
+                    let digits = path.get(index + 1..index + 3);
+                    if !digits.is_some_and(|digits| digits.bytes().all(|b| b.is_ascii_hexdigit())) {
+                        return Err(error(UrlErrorKind::InvalidPercentEncoding, position));
+                    }
+                    true
+                }
+                '#' if !fragment => {
+                    fragment = true;
+                    true
+                }
+                '/' | '?' => true,
+                c => is_pchar(c),
+            };
+            if allowed {
+                url.push(c);
+            } else if self.fix {
+                let mut buf = [0; 4];
+                for byte in c.encode_utf8(&mut buf).bytes() {
+                    url.push_str(&format!("%{byte:02X}"));
+                }
+            } else {
+                return Err(error(UrlErrorKind::IllegalCharacter(c), position));
+            }
+        }
+        Ok(url)
+    }
+}
+
+fn check_authority(authority: &str, offset: usize) -> std::result::Result<(), UrlError> {
+    let error = |kind, position| Err(UrlError { kind, position });
+    let (userinfo, host_port, host_offset) = match authority.rfind('@') {
+        Some(at) => (&authority[..at], &authority[at + 1..], offset + at + 1),
+        None => ("", authority, offset),
+    };
+    for (index, c) in userinfo.char_indices() {
+        if !(is_unreserved(c) || is_sub_delim(c) || matches!(c, ':' | '%')) {
+            return error(UrlErrorKind::IllegalCharacter(c), offset + index);
+        }
+    }
+
+    let (host, port) = if host_port.starts_with('[') {
+        let Some(close) = host_port.find(']') else {
+            return error(UrlErrorKind::InvalidHost, host_offset);
+        };
+        if host_port[1..close].parse::<std::net::Ipv6Addr>().is_err() {
+            return error(UrlErrorKind::InvalidHost, host_offset);
+        }
+        let port = &host_port[close + 1..];
+        match port.strip_prefix(':') {
+            Some(port) => ("", Some((port, host_offset + close + 2))),
+            None if port.is_empty() => ("", None),
+            None => return error(UrlErrorKind::InvalidHost, host_offset + close + 1),
+        }
+    } else {
+        match host_port.rfind(':') {
+            Some(colon) => (
+                &host_port[..colon],
+                Some((&host_port[colon + 1..], host_offset + colon + 1)),
+            ),
+            None => (host_port, None),
+        }
+    };
+    if host_port.is_empty() || (host.is_empty() && !host_port.starts_with('[')) {
+        return error(UrlErrorKind::EmptyHost, host_offset);
+    }
+    for (index, c) in host.char_indices() {
+        if !(is_unreserved(c) || is_sub_delim(c) || c == '%') {
+            return error(UrlErrorKind::IllegalCharacter(c), host_offset + index);
+        }
+    }
+    if let Some((port, position)) = port {
+        // An empty port is allowed and means the default one.
+        if !port.is_empty() && port.parse::<u16>().is_err() {
+            return error(UrlErrorKind::InvalidPort, position);
+        }
+    }
+    Ok(())
+}
+
+fn is_unreserved(c: char) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | '~')
+}
+
+fn is_sub_delim(c: char) -> bool {
+    matches!(
+        c,
+        '!' | '$' | '&' | '\'' | '(' | ')' | '*' | '+' | ',' | ';' | '='
+    )
+}
+
+fn is_pchar(c: char) -> bool {
+    is_unreserved(c) || is_sub_delim(c) || matches!(c, ':' | '@')
+}
+
+#[cfg(test)]
+mod test {
+    use super::{IntoUri, UrlError, UrlErrorKind, UrlParser};
+    use crate::Error;
+    use std::borrow::Cow;
+
+    fn error(parser: &UrlParser, input: &str) -> UrlError {
+        match parser.parse(input) {
+            Err(Error::InvalidUrl(error)) => error,
+            other => panic!("expected an invalid url, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn detailed_errors() {
+        let strict = UrlParser::new();
+        let cases = [
+            ("", UrlErrorKind::Empty, 0),
+            ("example.com/path", UrlErrorKind::MissingScheme, 0),
+            ("1http://example.com", UrlErrorKind::InvalidScheme, 0),
+            ("http://example.com:80a/", UrlErrorKind::InvalidPort, 19),
+            ("http://example.com:70000", UrlErrorKind::InvalidPort, 19),
+            ("http://:80/", UrlErrorKind::EmptyHost, 7),
+            ("http://[::g]/", UrlErrorKind::InvalidHost, 7),
+            ("http://a.com/a b", UrlErrorKind::IllegalCharacter(' '), 14),
+            ("http://a.com/%4", UrlErrorKind::InvalidPercentEncoding, 13),
+            (" http://a.com/", UrlErrorKind::InvalidScheme, 0),
+        ];
+        for (input, kind, position) in cases {
+            assert_eq!(
+                error(&strict, input),
+                UrlError { kind, position },
+                "{input:?}"
+            );
+        }
+        assert!(strict.parse("http://user@[::1]:8080/a%20b?q=1#top").is_ok());
+    }
+
+    #[test]
+    fn fixes() {
+        let lenient = UrlParser::new().fix().default_scheme("https");
+        let uri = lenient.parse("  example.com/search?q=a b  ").unwrap();
+        assert_eq!(uri.to_string(), "https://example.com/search?q=a%20b");
+        assert_eq!(
+            error(&lenient, " http://a.com:x/").position,
+            14,
+            "positions refer to the input"
+        );
+        let uri = lenient.parse("example.com/r?next=http://x").unwrap();
+        assert_eq!(uri.to_string(), "https://example.com/r?next=http://x");
+    }
+
+    #[test]
+    fn text_without_parser() {
+        assert!(matches!(
+            "/users".into_uri(None),
+            Ok(uri) if uri.path() == "/users"
+        ));
+        assert!(matches!(
+            "/users".into_uri(Some(&UrlParser::new())),
+            Err(Error::InvalidUrl(UrlError {
+                kind: UrlErrorKind::MissingScheme,
+                ..
+            }))
+        ));
+        assert!(Cow::Borrowed("http://example.com/").into_uri(None).is_ok());
+        assert!(b"http://example.com/".as_slice().into_uri(None).is_ok());
+        assert!(matches!("a b".into_uri(None), Err(Error::InvalidUri(_))));
+    }
+}
@@ -0,0 +1,230 @@
+//! API version negotiation.
+
+use crate::{Error, Middleware, Next, Result};
+use async_trait::async_trait;
+use http_kit::header::{self, HeaderName, HeaderValue};
+use http_kit::{Request, Response};
+use std::sync::Arc;
+
+/// The API version to ask for in one request, overriding the client's default.
+///
+/// Set it with [`RequestBuilder::api_version`](crate::RequestBuilder::api_version).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RequestedVersion(pub Arc<str>);
+
+/// The API version the server says it served, found in the response extensions.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ServedVersion(pub String);
+
+#[derive(Debug, Clone)]
+enum Scheme {
+    Header(HeaderName),
+    /// An `Accept` media type with a `{version}` placeholder.
+    MediaType(String),
+}
+
+/// Middleware asking every request for an API version, and detecting the version served.
+///
+/// The version is sent either in a header of its own or within the media type of `Accept`,
+/// such as `application/vnd.foo.v3+json`. Requests that already have the header of their own
+/// keep it. An `Accept` the request already has is kept as a fallback after the versioned
+/// media type, unless it asks for a version itself. The
+/// served version is read back from the same header or from a `Content-Type` of the same
+/// shape, or from [`Versioning::served_header`], and stored as a [`ServedVersion`].
+#[derive(Debug, Clone)]
+pub struct Versioning {
+    scheme: Scheme,
+    version: Arc<str>,
+    served_header: Option<HeaderName>,
+}
+
+impl Versioning {
+    /// Send `version` in the header `name`, e.g. `X-API-Version`.
+    pub fn header(name: HeaderName, version: &str) -> Self {
+        Self {
+            scheme: Scheme::Header(name),
+            version: version.into(),
+            served_header: None,
+        }
+    }
+
+    /// Send `version` in `Accept`, substituted for `{version}` in `template`, e.g.
+    /// `application/vnd.foo.v{version}+json` or `application/json; version={version}`.
+    pub fn media_type(template: &str, version: &str) -> Self {
+        Self {
+            scheme: Scheme::MediaType(template.to_owned()),
+            version: version.into(),
+            served_header: None,
+        }
+    }
+
+    /// Read the served version from the header `name`, e.g. `Api-Version`.
+    pub fn served_header(mut self, name: HeaderName) -> Self {
+        self.served_header = Some(name);
+        self
+    }
+
+    fn served(&self, response: &Response) -> Option<String> {
+        let header = |name: &HeaderName| {
+            let value = response.headers().get(name)?.to_str().ok()?.trim();
+            Some(value.to_owned())
+        };
+        if let Some(name) = &self.served_header {
+            return header(name);
+        }
+        match &self.scheme {
+            Scheme::Header(name) => header(name),
+            Scheme::MediaType(template) => {
+                let content_type = header(&header::CONTENT_TYPE)?;
+                version_of(template, &content_type).map(str::to_owned)
+            }
+        }
+    }
+}
+
+/// The version in `media_type` if it has the shape of `template`.
+fn version_of<'a>(template: &str, media_type: &'a str) -> Option<&'a str> {
+    let media_type = media_type.trim();
+    let (prefix, suffix) = template.split_once("{version}")?;
+    let version = media_type
+        .get(..prefix.len())
+        .filter(|start| start.eq_ignore_ascii_case(prefix))
+        .map(|_| &media_type[prefix.len()..])?;
+    let end = if suffix.is_empty() {
+        // Stop at the next parameter.
+        version.find(';').unwrap_or(version.len())
+    } else {
+        version.find(suffix)?
+    };
+    Some(version[..end].trim())
+}
+
+/// The `Accept` to send for `version`, or `None` to keep the request's own `accept`.
+fn accept(template: &str, version: &str, accept: Option<&HeaderValue>) -> Option<String> {
+    let versioned = template.replace("{version}", version);
+    let Some(accept) = accept else {
+        return Some(versioned);
+    };
+    let accept = accept.to_str().ok()?;
+    if accept
+        .split(',')
+        .any(|range| version_of(template, range).is_some())
+    {
+        return None;
+    }
+    Some(format!("{versioned}, {accept}"))
+}
+
+#[async_trait]
+impl Middleware for Versioning {
+    async fn handle(&self, request: &mut Request, next: Next<'_>) -> Result<Response> {
+        let version = match request.extensions().get::<RequestedVersion>() {
+            Some(requested) => requested.0.clone(),
+            None => self.version.clone(),
+        };
+        let header = match &self.scheme {
+            Scheme::Header(name) => {
+                (!request.headers().contains_key(name)).then(|| (name.clone(), version.to_string()))
+            }
+            Scheme::MediaType(template) => {
+                let value = accept(template, &version, request.headers().get(header::ACCEPT));
+                value.map(|value| (header::ACCEPT, value))
+            }
+        };
+        if let Some((name, value)) = header {
+            let value =
+                HeaderValue::try_from(value).map_err(|error| Error::InvalidHeader(error.into()))?;
+            request.insert_header(name, value);
+        }
+
+        let mut response = next.run(request).await?;
+        if let Some(served) = self.served(&response) {
+            response.extensions_mut().insert(ServedVersion(served));
+        }
+        Ok(response)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Versioning;
+    use crate::middleware::Capture;
+    use crate::{Middleware, Next};
+    use http_kit::header::{self, HeaderName, HeaderValue};
+    use http_kit::{Method, Request, Response};
+    use hyper::http;
+
+    /// The request headers `versioning` sends, given the request's own `headers`.
+    async fn sent(versioning: &Versioning, headers: &[(HeaderName, &'static str)]) -> Request {
+        let mut request = Request::new(Method::GET, "/");
+        for (name, value) in headers {
+            request.insert_header(name.clone(), HeaderValue::from_static(value));
+        }
+        let capture = Capture::default();
+        versioning
+            .handle(&mut request, Next::new(&[], &capture))
+            .await
+            .unwrap();
+        capture.into_request().unwrap()
+    }
+
+    #[tokio::test]
+    async fn requested_version() {
+        let vendor = Versioning::media_type("application/vnd.foo.v{version}+json", "3");
+        let accept = |request: Request| request.headers()[header::ACCEPT].clone();
+        assert_eq!(
+            accept(sent(&vendor, &[]).await),
+            "application/vnd.foo.v3+json"
+        );
+        assert_eq!(
+            accept(sent(&vendor, &[(header::ACCEPT, "application/json")]).await),
+            "application/vnd.foo.v3+json, application/json"
+        );
+        let explicit = [(header::ACCEPT, "text/plain, application/vnd.foo.v2+json")];
+        assert_eq!(
+            accept(sent(&vendor, &explicit).await),
+            "text/plain, application/vnd.foo.v2+json"
+        );
+
+        let parameter = Versioning::media_type("application/json; version={version}", "3");
+        assert_eq!(
+            accept(sent(&parameter, &[(header::ACCEPT, "application/json")]).await),
+            "application/json; version=3, application/json"
+        );
+
+        let name = HeaderName::from_static("api-version");
+        let versioning = Versioning::header(name.clone(), "3");
+        let request = sent(&versioning, &[(name.clone(), "2")]).await;
+        assert_eq!(request.headers()[&name], "2");
+    }
+
+    fn response(name: HeaderName, value: &'static str) -> Response {
+        let mut response: Response = http::Response::new(http_kit::Body::empty()).into();
+        response
+            .headers_mut()
+            .insert(name, HeaderValue::from_static(value));
+        response
+    }
+
+    #[test]
+    fn served_version() {
+        let vendor = Versioning::media_type("application/vnd.foo.v{version}+json", "3");
+        let served = vendor.served(&response(
+            header::CONTENT_TYPE,
+            "application/vnd.foo.v2+json; charset=utf-8",
+        ));
+        assert_eq!(served.as_deref(), Some("2"));
+
+        let parameter = Versioning::media_type("application/json; version={version}", "3");
+        let served = parameter.served(&response(
+            header::CONTENT_TYPE,
+            "application/json; version=3; charset=utf-8",
+        ));
+        assert_eq!(served.as_deref(), Some("3"));
+
+        let name = HeaderName::from_static("api-version");
+        let served =
+            Versioning::header(name.clone(), "2024-01-01").served(&response(name, "2023-06-01"));
+        assert_eq!(served.as_deref(), Some("2023-06-01"));
+    }
+}